@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A result saved by --cache-dir, with what the output needs besides it.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Entry {
+    pub result: f64,
+    /// The values read, leaving out the ones --ignore skipped
+    pub count: usize,
+    /// The values counted for --template, with the smallest and largest of them
+    pub summary: (usize, Option<(f64, f64)>),
+    /// The most decimal places of any value, for --match-input-precision
+    pub decimals: usize,
+}
+
+/// The name the result of a run is saved under: an FNV-1a hash of what it depends on besides
+/// its input, as `args`, and the contents of its input files, so changing either misses the cache.
+pub fn key(args: &[String], files: &[&Path]) -> io::Result<String> {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let hash = |hash: u64, bytes: &[u8]| bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(PRIME));
+    // the lengths go in too, so moving bytes from one part to the next changes the hash
+    let mut key = OFFSET_BASIS;
+    for arg in args {
+        key = hash(hash(key, &arg.len().to_le_bytes()), arg.as_bytes());
+    }
+    for file in files {
+        let contents = fs::read(file)?;
+        key = hash(hash(key, &contents.len().to_le_bytes()), &contents);
+    }
+    Ok(format!("{:016x}", key))
+}
+
+fn path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(key)
+}
+
+impl Entry {
+    /// Read the entry saved under `key`, `None` if there isn't one. A corrupt entry is reported and ignored.
+    pub fn load(dir: &Path, key: &str) -> Option<Entry> {
+        let path = path(dir, key);
+        let contents = fs::read_to_string(&path).ok()?;
+        let entry = Entry::read(&contents);
+        if entry.is_none() {
+            log::warn!("Ignoring corrupt cache entry {}", path.display());
+        }
+        entry
+    }
+
+    fn read(contents: &str) -> Option<Entry> {
+        let fields: Vec<&str> = contents.split_whitespace().collect();
+        let (result, count, counted, min, max, decimals) = match fields[..] {
+            [result, count, counted, min, max, decimals] => (result, count, counted, min, max, decimals),
+            _ => return None
+        };
+        let extent = match (min, max) {
+            ("-", "-") => None,
+            _ => Some((min.parse().ok()?, max.parse().ok()?))
+        };
+        Some(Entry {
+            result: result.parse().ok()?,
+            count: count.parse().ok()?,
+            summary: (counted.parse().ok()?, extent),
+            decimals: decimals.parse().ok()?,
+        })
+    }
+
+    /// Save the entry under `key`, writing it next to its place first so a reader never sees half of it.
+    pub fn save(self, dir: &Path, key: &str) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let path = path(dir, key);
+        let tmp = path.with_extension("tmp");
+        let (counted, extent) = self.summary;
+        let (min, max) = extent.map_or(("-".to_string(), "-".to_string()), |(min, max)| (min.to_string(), max.to_string()));
+        fs::write(&tmp, format!("{} {} {} {} {} {}\n", self.result, self.count, counted, min, max, self.decimals))?;
+        fs::rename(&tmp, path)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Entry, key};
+    use std::fs;
+
+    #[test]
+    fn test_hit_and_miss() {
+        let dir = std::env::temp_dir().join("mathcli_test_cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input");
+        fs::write(&input, "1\n2\n").unwrap();
+        let args = vec!["mathcli".to_string(), "add".to_string()];
+        let cached = dir.join("cache");
+        let first = key(&args, &[&input]).unwrap();
+        assert_eq!(None, Entry::load(&cached, &first));
+        let entry = Entry { result: 1. / 3., count: 2, summary: (2, Some((-1.5, 2.))), decimals: 1 };
+        entry.save(&cached, &first).unwrap();
+        assert_eq!(Some(entry), Entry::load(&cached, &first));
+        // the same run again hits
+        assert_eq!(first, key(&args, &[&input]).unwrap());
+        // another operation or changed contents miss
+        assert_ne!(first, key(&["mathcli".to_string(), "mul".to_string()], &[&input]).unwrap());
+        fs::write(&input, "1\n3\n").unwrap();
+        let changed = key(&args, &[&input]).unwrap();
+        assert_ne!(first, changed);
+        assert_eq!(None, Entry::load(&cached, &changed));
+        let empty = Entry { result: 0., count: 1, summary: (0, None), decimals: 0 };
+        empty.save(&cached, &changed).unwrap();
+        assert_eq!(Some(empty), Entry::load(&cached, &changed));
+        fs::write(cached.join(&changed), "0 1").unwrap();
+        assert_eq!(None, Entry::load(&cached, &changed));
+        assert!(key(&args, &[&dir.join("missing")]).is_err());
+    }
+
+    #[test]
+    fn test_key_separates_arguments() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        assert_ne!(key(&args(&["--ignore", "12", "add"]), &[]).unwrap(), key(&args(&["--ignore", "1", "2add"]), &[]).unwrap());
+    }
+}
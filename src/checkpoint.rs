@@ -0,0 +1,121 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The running result after `count` values, saved so an interrupted run can pick up where it left off.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Checkpoint {
+    pub result: f64,
+    pub count: usize,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint, `None` if there isn't one. A corrupt checkpoint is reported and ignored.
+    pub fn load(path: &Path) -> Option<Checkpoint> {
+        let contents = fs::read_to_string(path).ok()?;
+        let mut fields = contents.split_whitespace();
+        let checkpoint = match (fields.next(), fields.next(), fields.next()) {
+            (Some(result), Some(count), None) => result.parse().ok()
+                .and_then(|result| count.parse().ok().map(|count| Checkpoint { result, count })),
+            _ => None
+        };
+        if checkpoint.is_none() {
+            log::warn!("Ignoring corrupt checkpoint {}", path.display());
+        }
+        checkpoint
+    }
+
+    /// Write the checkpoint next to `path` and move it into place, so a reader never sees half of it.
+    pub fn save(self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, format!("{} {}\n", self.result, self.count))?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Fold `values` with `operator`, saving a checkpoint to `path` every `every` values and at the end.
+/// An `every` of 0 only saves at the end.
+/// When resuming, the values the checkpoint already accounts for are skipped.
+pub fn fold(
+    values: impl Iterator<Item=f64>,
+    operator: fn(f64, f64) -> f64,
+    start: Option<f64>,
+    path: &Path,
+    every: usize,
+    resume: Option<Checkpoint>,
+) -> Option<f64> {
+    let (mut acc, mut count) = match resume {
+        Some(checkpoint) => {
+            log::info!("Resuming from {} after {} values", checkpoint.result, checkpoint.count);
+            (Some(checkpoint.result), checkpoint.count)
+        },
+        None => (start, 0)
+    };
+    for val in values.skip(count) {
+        let result = acc.map_or(val, |acc| operator(acc, val));
+        acc = Some(result);
+        count += 1;
+        if every != 0 && count % every == 0 {
+            save(Checkpoint { result, count }, path);
+        }
+    }
+    if let Some(result) = acc {
+        save(Checkpoint { result, count }, path);
+    }
+    acc
+}
+
+fn save(checkpoint: Checkpoint, path: &Path) {
+    if let Err(e) = checkpoint.save(path) {
+        log::warn!("Failed to write checkpoint {}: {}", path.display(), e);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Checkpoint, fold};
+    use std::fs;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join("mathcli_test_save_and_load");
+        let checkpoint = Checkpoint { result: 12.5, count: 3 };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Some(checkpoint), Checkpoint::load(&path));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(None, Checkpoint::load(&path));
+    }
+
+    #[test]
+    fn test_load_corrupt() {
+        let path = std::env::temp_dir().join("mathcli_test_load_corrupt");
+        for contents in &["", "12.5", "12.5 3 4", "12.5 three", "12.5 -3"] {
+            fs::write(&path, contents).unwrap();
+            assert_eq!(None, Checkpoint::load(&path));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resume() {
+        let path = std::env::temp_dir().join("mathcli_test_resume");
+        let input = vec![10., 1., 2., 3.];
+        // interrupted after two values
+        fold(input.clone().into_iter().take(2), std::ops::Sub::sub, None, &path, 1, None);
+        assert_eq!(Some(Checkpoint { result: 9., count: 2 }), Checkpoint::load(&path));
+        let resumed = fold(input.into_iter(), std::ops::Sub::sub, None, &path, 1, Checkpoint::load(&path));
+        assert_eq!(Some(4.), resumed);
+        assert_eq!(Some(Checkpoint { result: 4., count: 4 }), Checkpoint::load(&path));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_at_end() {
+        let path = std::env::temp_dir().join("mathcli_test_checkpoint_at_end");
+        fold(vec![1., 2., 3.].into_iter(), std::ops::Add::add, Some(0.), &path, 2, None);
+        assert_eq!(Some(Checkpoint { result: 6., count: 3 }), Checkpoint::load(&path));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+/// The unit durations are given in.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DurationUnit {
+    Millis,
+    Seconds,
+}
+
+impl DurationUnit {
+    fn millis(self) -> f64 {
+        match self {
+            DurationUnit::Millis => 1.,
+            DurationUnit::Seconds => 1000.
+        }
+    }
+}
+
+impl FromStr for DurationUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "ms" => Ok(DurationUnit::Millis),
+            "s" => Ok(DurationUnit::Seconds),
+            _ => Err(format!("Unknown duration unit {}, options are ms, s", s))
+        }
+    }
+}
+
+/// Read a duration like `1h`, `1m30s` or `250ms` as a number of seconds.
+/// A plain number is taken to be seconds already.
+pub fn parse(s: &str) -> Result<f64, String> {
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Ok(seconds)
+    }
+    let (sign, mut rest) = match (s.strip_prefix('-'), s.strip_prefix('+')) {
+        (Some(rest), _) => (-1., rest),
+        (None, Some(rest)) => (1., rest),
+        (None, None) => (1., s)
+    };
+    if rest.is_empty() {
+        return Err(format!("invalid duration {}", s))
+    }
+    let mut seconds = 0.;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        let unit_end = rest[number_end..].find(|c: char| !c.is_ascii_alphabetic()).map_or(rest.len(), |i| number_end + i);
+        let number: f64 = rest[..number_end].parse().map_err(|_| format!("invalid duration {}", s))?;
+        let size = match &rest[number_end..unit_end] {
+            "h" => 3600.,
+            "m" => 60.,
+            "s" => 1.,
+            "ms" => 0.001,
+            _ => return Err(format!("invalid duration {}", s))
+        };
+        seconds += number * size;
+        rest = &rest[unit_end..];
+    }
+    Ok(sign * seconds)
+}
+
+/// Write `value` in `unit` as hours, minutes, seconds and milliseconds, e.g. `1m 23s 500ms`.
+/// Parts that are zero are left out, anything under a millisecond is rounded away.
+pub fn humanize(value: f64, unit: DurationUnit) -> String {
+    if !value.is_finite() {
+        return value.to_string()
+    }
+    let millis = (value * unit.millis()).round();
+    let sign = if millis < 0. { "-" } else { "" };
+    let mut rest = millis.abs() as u64;
+    let mut parts = Vec::new();
+    for (size, suffix) in &[(3_600_000, "h"), (60_000, "m"), (1000, "s"), (1, "ms")] {
+        let count = rest / size;
+        rest %= size;
+        if count > 0 {
+            parts.push(format!("{}{}", count, suffix));
+        }
+    }
+    if parts.is_empty() {
+        return "0s".to_string()
+    }
+    format!("{}{}", sign, parts.join(" "))
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{DurationUnit, humanize, parse};
+
+    #[test]
+    fn test_humanize_seconds() {
+        assert_eq!("1m 23s", humanize(83., DurationUnit::Seconds));
+        assert_eq!("2h 5s", humanize(7205., DurationUnit::Seconds));
+        assert_eq!("26h 1m", humanize(93660., DurationUnit::Seconds));
+        assert_eq!("1s 500ms", humanize(1.5, DurationUnit::Seconds));
+    }
+
+    #[test]
+    fn test_humanize_millis() {
+        assert_eq!("1m 23s", humanize(83000., DurationUnit::Millis));
+        assert_eq!("250ms", humanize(250., DurationUnit::Millis));
+        assert_eq!("1ms", humanize(0.6, DurationUnit::Millis));
+    }
+
+    #[test]
+    fn test_humanize_zero_and_negative() {
+        assert_eq!("0s", humanize(0., DurationUnit::Seconds));
+        assert_eq!("0s", humanize(0.0001, DurationUnit::Seconds));
+        assert_eq!("-1m 30s", humanize(-90., DurationUnit::Seconds));
+    }
+
+    #[test]
+    fn test_unit_from_str() {
+        assert_eq!(Ok(DurationUnit::Millis), "ms".parse());
+        assert_eq!(Ok(DurationUnit::Seconds), "s".parse());
+        assert!("min".parse::<DurationUnit>().is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(3600.), parse("1h"));
+        assert_eq!(Ok(90.), parse("90s"));
+        assert_eq!(Ok(0.25), parse("250ms"));
+        assert_eq!(Ok(90.), parse("1m30s"));
+        assert_eq!(Ok(3661.5), parse("1h1m1s500ms"));
+        assert_eq!(Ok(-90.), parse("-1.5m"));
+        assert_eq!(Ok(90.), parse("+1m30s"));
+        assert_eq!(Ok(12.), parse("12"));
+        assert_eq!(Ok(42.), parse("+42"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for token in &["", "-", "+", "+-1s", "abc", "5x", "m", "1m30", "1.2.3s", "1 m"] {
+            assert_eq!(Err(format!("invalid duration {}", token)), parse(token));
+        }
+    }
+}
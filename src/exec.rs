@@ -0,0 +1,137 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+
+/// The commands started by `CommandOutput::spawn`, for `wait_all` to wait for at the end of the run.
+static STARTED: Mutex<Vec<Started>> = Mutex::new(Vec::new());
+
+/// The standard output of a command run for --exec, read like a file.
+/// The command is waited for separately, see `Started` and `wait_all`.
+pub struct CommandOutput {
+    stdout: BufReader<ChildStdout>,
+}
+
+/// A command that was started, to be waited for once the run is done with its output.
+pub struct Started {
+    command: String,
+    child: Child,
+}
+
+/// SIGPIPE, which the command gets when it writes after the run stopped reading.
+const SIGPIPE: i32 = 13;
+
+impl CommandOutput {
+    /// Start `command` with the shell, leaving it for `wait_all`.
+    pub fn spawn(command: &str) -> io::Result<CommandOutput> {
+        let (output, started) = CommandOutput::start(command)?;
+        STARTED.lock().unwrap_or_else(|e| e.into_inner()).push(started);
+        Ok(output)
+    }
+
+    /// Start `command` with the shell, handing back what's needed to wait for it.
+    pub fn start(command: &str) -> io::Result<(CommandOutput, Started)> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok((CommandOutput { stdout: BufReader::new(stdout) }, Started { command: command.to_string(), child }))
+    }
+}
+
+impl Started {
+    /// Wait for the command to exit, once its `CommandOutput` is dropped. A command still writing
+    /// output the run stopped short of, like everything after an empty line, then dies of SIGPIPE,
+    /// which isn't a failure. Any other failure is an error with its message and the exit code to
+    /// pass on.
+    pub fn wait(mut self) -> Result<(), (String, i32)> {
+        let status = self.child.wait().map_err(|e| (format!("Failed to wait for {}: {}", self.command, e), 1))?;
+        match status.success() || piped(status) {
+            true => Ok(()),
+            false => Err((format!("Command {} failed: {}", self.command, status), status.code().unwrap_or(1)))
+        }
+    }
+}
+
+/// Whether the command was ended by SIGPIPE, itself or, as the shell reports it, the last
+/// command it ran.
+fn piped(status: ExitStatus) -> bool {
+    status.signal() == Some(SIGPIPE) || status.code() == Some(128 + SIGPIPE)
+}
+
+/// Wait for every command started for --exec, failing with the first that failed. Their
+/// output has to be dropped by now, or a command that never stops writing is never done.
+pub fn wait_all() -> Result<(), (String, i32)> {
+    let started = std::mem::take(&mut *STARTED.lock().unwrap_or_else(|e| e.into_inner()));
+    // every one is waited for, even after one failed
+    let waited: Vec<_> = started.into_iter().map(Started::wait).collect();
+    waited.into_iter().collect()
+}
+
+impl Read for CommandOutput {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(out)
+    }
+}
+
+impl BufRead for CommandOutput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.stdout.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.stdout.consume(amt)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::CommandOutput;
+    use std::io::BufRead;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_read_command_output() {
+        let (output, started) = CommandOutput::start("echo 1; echo 2.5").unwrap();
+        let lines: Vec<String> = output.lines().map(Result::unwrap).collect();
+        assert_eq!(vec!["1", "2.5"], lines);
+        assert_eq!(Ok(()), started.wait());
+    }
+
+    #[test]
+    fn test_empty_command_output() {
+        let (output, started) = CommandOutput::start("true").unwrap();
+        assert_eq!(0, output.lines().count());
+        assert_eq!(Ok(()), started.wait());
+    }
+
+    #[test]
+    fn test_failure_after_empty_line() {
+        // the run stops reading at the empty line, long before the command exits
+        let (output, started) = CommandOutput::start("printf '1\\n2\\n\\n'; seq 100000; exit 3").unwrap();
+        let lines: Vec<String> = output.lines().map(Result::unwrap).take_while(|line| !line.is_empty()).collect();
+        assert_eq!(vec!["1", "2"], lines);
+        let (message, code) = started.wait().unwrap_err();
+        assert_eq!(3, code);
+        assert!(message.starts_with("Command printf"), "{}", message);
+    }
+
+    #[test]
+    fn test_endless_command_finishes() {
+        for command in ["yes 1", "printf '1\\n\\n'; yes 2"] {
+            let (output, started) = CommandOutput::start(command).unwrap();
+            assert_eq!(Some("1".to_string()), output.lines().map(Result::unwrap).next());
+            // the output is dropped, the command has nowhere left to write
+            let (done, waited) = mpsc::channel();
+            thread::spawn(move || done.send(started.wait()).unwrap());
+            assert_eq!(Ok(Ok(())), waited.recv_timeout(Duration::from_secs(10)), "{}", command);
+        }
+    }
+}
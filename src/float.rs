@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+/// The width of the floats values are read and combined as. Values are carried as `f64` either
+/// way; with 32 bits every value and every intermediate result is rounded to an `f32`, so the
+/// results are those of working in `f32` throughout.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FloatBits {
+    Single,
+    Double,
+}
+
+impl FloatBits {
+    /// Round `val` to the nearest value of this width.
+    pub fn round(self, val: f64) -> f64 {
+        match self {
+            FloatBits::Single => val as f32 as f64,
+            FloatBits::Double => val
+        }
+    }
+
+    /// Read `s` straight into this width, rather than rounding twice through an `f64`.
+    pub fn parse(self, s: &str) -> Result<f64, String> {
+        match self {
+            FloatBits::Single => s.parse::<f32>().map(f64::from),
+            FloatBits::Double => s.parse::<f64>()
+        }.map_err(|e| e.to_string())
+    }
+
+    /// The shortest text that reads back as `val` at this width.
+    pub fn display(self, val: f64) -> String {
+        match self {
+            FloatBits::Single => (val as f32).to_string(),
+            FloatBits::Double => val.to_string()
+        }
+    }
+}
+
+impl FromStr for FloatBits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "32" => Ok(FloatBits::Single),
+            "64" => Ok(FloatBits::Double),
+            _ => Err(format!("Unsupported float width {}, options are 32, 64", s))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::FloatBits;
+
+    #[test]
+    fn test_round() {
+        assert_eq!(16777216., FloatBits::Single.round(16777217.));
+        assert_eq!(16777217., FloatBits::Double.round(16777217.));
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(Ok(0.1f32 as f64), FloatBits::Single.parse("0.1"));
+        assert_eq!("0.1", FloatBits::Single.display(0.1f32 as f64));
+        assert_eq!("0.10000000149011612", FloatBits::Double.display(0.1f32 as f64));
+        assert!(FloatBits::Double.parse("x").is_err());
+    }
+
+    #[test]
+    fn test_bits_from_str() {
+        assert_eq!(Ok(FloatBits::Single), "32".parse());
+        assert_eq!(Ok(FloatBits::Double), "64".parse());
+        assert!("16".parse::<FloatBits>().is_err());
+    }
+}
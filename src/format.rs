@@ -0,0 +1,653 @@
+use crate::Opts;
+use crate::duration::{self, DurationUnit};
+use crate::float::FloatBits;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Responsible for turning the final result into the text we print
+#[derive(Copy, Clone)]
+pub struct OutputFormatter {
+    thousands_separator: Option<char>,
+    csv: bool,
+    precision: Option<usize>,
+    duration: Option<DurationUnit>,
+    sign_always: bool,
+    bits: FloatBits,
+    delimiter: Option<char>,
+    locale: Option<Locale>,
+    coerce_int: bool,
+    engineering: bool,
+}
+
+impl OutputFormatter {
+    /// Use the output options to configure the formatter.
+    pub fn new(opts: &Opts) -> Self {
+        OutputFormatter {
+            thousands_separator: match opts.format_thousands {
+                true => Some(opts.thousands_separator),
+                false => None
+            },
+            csv: opts.csv_output,
+            precision: None,
+            duration: opts.output_as_duration,
+            sign_always: opts.output_sign_always,
+            bits: opts.float_bits,
+            delimiter: opts.output_delimiter,
+            locale: opts.output_locale,
+            coerce_int: opts.coerce_int,
+            engineering: opts.engineering
+        }
+    }
+
+    /// Print `decimals` decimal places rather than as many as the value needs.
+    pub fn with_precision(self, decimals: usize) -> Self {
+        OutputFormatter { precision: Some(decimals), ..self }
+    }
+
+    pub fn format(self, value: f64) -> String {
+        if let Some(unit) = self.duration {
+            return duration::humanize(value, unit)
+        }
+        if self.engineering {
+            return self.engineering(value)
+        }
+        let formatted = self.write_bits(value);
+        match (self.locale, self.thousands_separator) {
+            (Some(locale), _) => locale.write(&formatted),
+            (None, Some(sep)) => group_thousands(&formatted, sep),
+            (None, None) => formatted
+        }
+    }
+
+    fn write_bits(self, value: f64) -> String {
+        // an f32 has a shorter shortest representation than the same value widened to an f64
+        match self.bits {
+            FloatBits::Single => self.write(value as f32),
+            FloatBits::Double => self.write(value)
+        }
+    }
+
+    /// Write `value` with an exponent that's a multiple of 3 and a mantissa from 1 up to 1000,
+    /// like 12.3e3. The precision is the number of decimal places of the mantissa.
+    fn engineering(self, value: f64) -> String {
+        if !value.is_finite() {
+            return self.write_bits(value)
+        }
+        let mut exponent = match value == 0. {
+            true => 0,
+            false => (value.abs().log10() / 3.).floor() as i32 * 3
+        };
+        let mut mantissa = self.write_bits(value / 10f64.powi(exponent));
+        // rounding to the precision can carry the mantissa up to 1000
+        if mantissa.parse::<f64>().is_ok_and(|m| m.abs() >= 1000.) {
+            exponent += 3;
+            mantissa = self.write_bits(value / 10f64.powi(exponent));
+        }
+        format!("{}e{}", mantissa, exponent)
+    }
+
+    fn write(self, value: impl Display + Into<f64> + Copy) -> String {
+        let whole = self.coerce_int && value.into().fract() == 0.;
+        match (self.precision.filter(|_| !whole), self.sign_always) {
+            (Some(decimals), true) => format!("{:+.*}", decimals, value),
+            (Some(decimals), false) => format!("{:.*}", decimals, value),
+            (None, true) => format!("{:+}", value),
+            (None, false) => value.to_string()
+        }
+    }
+
+    /// Format one line of a transform's output, `unit` is appended to the transformed value.
+    /// With --csv-output the line is `original,transformed` instead, without the unit and with
+    /// the --output-delimiter for the comma if there is one. Numbers holding the delimiter, like
+    /// `1,234.5` with --format-thousands, are quoted.
+    pub fn format_transformed(self, original: f64, transformed: f64, unit: &str) -> String {
+        match self.csv {
+            true => csv_line(&[self.format(original), self.format(transformed)], self.delimiter.unwrap_or(',')),
+            false => format!("{}{}", self.format(transformed), unit)
+        }
+    }
+
+    /// Write `value` relative to `reference` for --output-ratio, like `3:2` for 6 and 4.
+    /// Whole numbers are reduced to lowest terms, anything else is written as `ratio:1`.
+    /// With `decimal` the ratio as a single number follows in brackets.
+    pub fn ratio(self, value: f64, reference: f64, decimal: bool) -> Result<String, String> {
+        if reference == 0. {
+            return Err(format!("Can't take the ratio of {} to 0", self.format(value)))
+        }
+        let ratio = match (whole(value), whole(reference)) {
+            (Some(a), Some(b)) => {
+                let divisor = gcd(a.unsigned_abs(), b.unsigned_abs()) as i64;
+                let (a, b) = (a / divisor, b / divisor);
+                // keep any sign on the left
+                match b < 0 {
+                    true => format!("{}:{}", -a, -b),
+                    false => format!("{}:{}", a, b)
+                }
+            },
+            _ => format!("{}:1", self.format(value / reference))
+        };
+        Ok(match decimal {
+            true => format!("{} ({})", ratio, self.format(value / reference)),
+            false => ratio
+        })
+    }
+
+    /// Write `value` in one of the --also formats.
+    pub fn also(self, value: f64, format: Also) -> Result<String, String> {
+        match format {
+            Also::Decimal => Ok(self.format(value)),
+            Also::Scientific => Ok(match self.bits {
+                FloatBits::Single => format!("{:e}", value as f32),
+                FloatBits::Double => format!("{:e}", value)
+            }),
+            Also::Hex => hex(value),
+            Also::Fraction => fraction(&self.bits.display(value))
+        }
+    }
+
+    /// Join the fields of one line of multi-field output with the --output-delimiter.
+    pub fn join(self, fields: &[String]) -> String {
+        fields.join(self.delimiter.unwrap_or(' ').encode_utf8(&mut [0; 4]))
+    }
+}
+
+/// Write `fields` as one CSV line, without the line ending. The csv writer only takes an ASCII
+/// delimiter, any other is joined on as is.
+fn csv_line(fields: &[String], delimiter: char) -> String {
+    if !delimiter.is_ascii() {
+        return fields.join(delimiter.encode_utf8(&mut [0; 4]))
+    }
+    let mut line = csv::WriterBuilder::new()
+        .delimiter(delimiter as u8)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    line.write_record(fields).expect("writing to memory can't fail");
+    let line = line.into_inner().expect("writing to memory can't fail");
+    String::from_utf8(line).expect("the fields are UTF-8").trim_end_matches('\n').to_string()
+}
+
+/// The decimal point and digit grouping of a language, for --output-locale.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Locale {
+    decimal: char,
+    group: char,
+}
+
+impl Locale {
+    /// Rewrite a number formatted with a `.` decimal point and no grouping in this locale.
+    fn write(self, formatted: &str) -> String {
+        let (int_part, frac_part) = match formatted.find('.') {
+            Some(i) => (&formatted[..i], Some(&formatted[i + 1..])),
+            None => (formatted, None)
+        };
+        let mut written = group_thousands(int_part, self.group);
+        if let Some(frac_part) = frac_part {
+            written.push(self.decimal);
+            written.push_str(frac_part);
+        }
+        written
+    }
+}
+
+impl Locale {
+    /// Rewrite a number written in this locale with a `.` decimal point and no grouping, the
+    /// other way round from `write`. A locale grouping with spaces takes any kind of space.
+    pub fn read(self, written: &str) -> String {
+        written.chars()
+            .filter(|c| *c != self.group && !(self.group == ' ' && c.is_whitespace()))
+            .map(|c| if c == self.decimal { '.' } else { c })
+            .collect()
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Only the language matters, so `de`, `de_DE` and `de-AT.UTF-8` all read the same.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let language = s.split(['_', '-', '.']).next().unwrap_or(s);
+        let (decimal, group) = match language.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => ('.', ','),
+            "de" | "nl" | "it" | "es" | "pt" | "da" | "id" => (',', '.'),
+            "fr" | "sv" | "fi" | "nb" | "no" | "pl" | "cs" | "ru" => (',', ' '),
+            _ => return Err(format!("Unsupported locale {}, use e.g. en, de or fr", s))
+        };
+        Ok(Locale { decimal, group })
+    }
+}
+
+/// An extra way to write the result for --also.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Also {
+    Decimal,
+    Scientific,
+    Hex,
+    Fraction,
+}
+
+impl FromStr for Also {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "decimal" => Ok(Also::Decimal),
+            "scientific" => Ok(Also::Scientific),
+            "hex" => Ok(Also::Hex),
+            "fraction" => Ok(Also::Fraction),
+            _ => Err(format!("Unknown format {}, options are decimal, scientific, hex, fraction", s))
+        }
+    }
+}
+
+/// The result line written with --template, like `Total: {result} over {count} items`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Template(String);
+
+impl Template {
+    /// The names that can go in braces.
+    const PLACEHOLDERS: [&'static str; 5] = ["result", "count", "operation", "min", "max"];
+
+    /// The template with each `{name}` in `values` replaced by its value.
+    pub fn fill(&self, values: &[(&str, String)]) -> String {
+        values.iter().fold(self.0.clone(), |filled, (name, value)| filled.replace(&format!("{{{}}}", name), value))
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..].find('}').ok_or_else(|| format!("Unclosed {{ in template {}", s))?;
+            let name = &rest[open + 1..open + close];
+            if !Template::PLACEHOLDERS.contains(&name) {
+                return Err(format!("Unknown placeholder {{{}}}, options are {}", name,
+                    Template::PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")))
+            }
+            rest = &rest[open + close + 1..];
+        }
+        Ok(Template(s.to_string()))
+    }
+}
+
+/// The line ending written after every line of output, for --line-ending.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(format!("Unknown line ending {}, options are lf, crlf", s))
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineEnding::Lf => f.write_str("\n"),
+            LineEnding::Crlf => f.write_str("\r\n")
+        }
+    }
+}
+
+/// Render `rows` under `headers` as an ASCII table, every column as wide as its widest cell.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[&str]| cells.iter().zip(&widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<String>>()
+        .join(" | ")
+        .trim_end()
+        .to_string();
+    let mut lines = Vec::with_capacity(rows.len() + 2);
+    lines.push(line(headers));
+    lines.push(widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<String>>().join("-+-"));
+    for row in rows {
+        lines.push(line(&row.iter().map(String::as_str).collect::<Vec<&str>>()));
+    }
+    lines
+}
+
+/// Right align the whitespace separated fields of `lines` into columns as wide as their widest field.
+pub fn align(lines: &[String]) -> Vec<String> {
+    let rows: Vec<Vec<&str>> = lines.iter().map(|line| line.split_whitespace().collect()).collect();
+    let mut widths: Vec<usize> = Vec::new();
+    for row in &rows {
+        for (i, field) in row.iter().enumerate() {
+            match widths.get_mut(i) {
+                Some(width) => *width = (*width).max(field.chars().count()),
+                None => widths.push(field.chars().count())
+            }
+        }
+    }
+    rows.iter().map(|row| row.iter().zip(&widths)
+        .map(|(field, width)| format!("{:>width$}", field, width = width))
+        .collect::<Vec<String>>()
+        .join(" "))
+    .collect()
+}
+
+/// Write a whole number in hexadecimal for --output-hex, e.g. `0xff` or `-0x10`.
+pub fn hex(value: f64) -> Result<String, String> {
+    let int = match whole(value) {
+        Some(int) => int,
+        // any f64 this big is a whole number, it just doesn't fit in an i64
+        None if value.is_finite() && value.fract() == 0. => return Err(format!("Can't print {} in hex, it's out of range for hex output", value)),
+        None => return Err(format!("Can't print {} in hex, it isn't a whole number", value))
+    };
+    Ok(match int < 0 {
+        true => format!("-{:#x}", int.unsigned_abs()),
+        false => format!("{:#x}", int)
+    })
+}
+
+/// Write a decimal number like `-0.375` as a fraction in lowest terms, `-3/8`. Whole numbers are
+/// written over 1.
+fn fraction(decimal: &str) -> Result<String, String> {
+    let unrepresentable = || format!("Can't write {} as a fraction", decimal);
+    let (sign, unsigned) = match decimal.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", decimal)
+    };
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if !unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(unrepresentable())
+    }
+    let numerator: u64 = format!("{}{}", int_part, frac_part).parse().map_err(|_| unrepresentable())?;
+    let denominator = 10u64.checked_pow(frac_part.len() as u32).ok_or_else(unrepresentable)?;
+    let divisor = gcd(numerator, denominator);
+    Ok(format!("{}{}/{}", sign, numerator / divisor, denominator / divisor))
+}
+
+/// `value` as an integer, if it is a whole number that fits in one.
+fn whole(value: f64) -> Option<i64> {
+    match value.is_finite() && value.fract() == 0. && value.abs() < i64::MAX as f64 {
+        true => Some(value as i64),
+        false => None
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b)
+    }
+}
+
+/// Insert `sep` between every three digits of the integer part of a formatted number.
+/// Anything that isn't a plain number (inf, NaN) is returned as is.
+fn group_thousands(formatted: &str, sep: char) -> String {
+    let (sign, unsigned) = match formatted.find(|c| c != '-' && c != '+') {
+        Some(i) => formatted.split_at(i),
+        None => ("", formatted)
+    };
+    let (int_part, frac_part) = match unsigned.find('.') {
+        Some(i) => unsigned.split_at(i),
+        None => (unsigned, "")
+    };
+    if !int_part.chars().all(|c| c.is_ascii_digit()) {
+        return formatted.to_string()
+    }
+    let mut grouped = String::with_capacity(formatted.len() + int_part.len() / 3);
+    grouped.push_str(sign);
+    for (i, digit) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(sep);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(frac_part);
+    grouped
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Also, Locale, OutputFormatter, Template, align, fraction, hex, table};
+    use crate::duration::DurationUnit;
+    use crate::float::FloatBits;
+
+    fn formatter(thousands_separator: Option<char>) -> OutputFormatter {
+        OutputFormatter { thousands_separator, csv: false, precision: None, duration: None, sign_always: false, bits: FloatBits::Single, delimiter: None, locale: None, coerce_int: false, engineering: false }
+    }
+
+    #[test]
+    fn test_no_grouping_by_default() {
+        assert_eq!("1234567", formatter(None).format(1234567.));
+    }
+
+    #[test]
+    fn test_group_large_sum() {
+        let formatter = formatter(Some(','));
+        assert_eq!("1,234,567", formatter.format(1234567.));
+        assert_eq!("123,456", formatter.format(123456.));
+        assert_eq!("999", formatter.format(999.));
+        assert_eq!("-1,000", formatter.format(-1000.));
+    }
+
+    #[test]
+    fn test_group_only_integer_part() {
+        assert_eq!("1,234.5", formatter(Some(',')).format(1234.5));
+        assert_eq!("65 536.25", formatter(Some(' ')).format(65536.25));
+    }
+
+    #[test]
+    fn test_group_leaves_non_finite() {
+        assert_eq!("inf", formatter(Some(',')).format(f64::INFINITY));
+        assert_eq!("NaN", formatter(Some(',')).format(f64::NAN));
+    }
+
+    #[test]
+    fn test_format_transformed() {
+        assert_eq!("25%", formatter(None).format_transformed(1., 25., "%"));
+    }
+
+    #[test]
+    fn test_format_transformed_csv() {
+        let formatter = OutputFormatter { csv: true, ..formatter(None) };
+        let lines: Vec<String> = [(1., 25.), (3., 75.)].iter()
+            .map(|(original, percent)| formatter.format_transformed(*original, *percent, "%"))
+            .collect();
+        assert_eq!(vec!["1,25", "3,75"], lines);
+    }
+
+    #[test]
+    fn test_format_transformed_csv_quotes_separators() {
+        let grouped = OutputFormatter { csv: true, ..formatter(Some(',')) };
+        assert_eq!("\"1,234.5\",25", grouped.format_transformed(1234.5, 25., "%"));
+        let german = OutputFormatter { csv: true, locale: Some("de".parse().unwrap()), ..formatter(None) };
+        assert_eq!("\"2,5\",1.000", german.format_transformed(2.5, 1000., "%"));
+        // nothing to quote when the delimiter can't turn up in a number
+        let tabs = OutputFormatter { delimiter: Some('\t'), ..grouped };
+        assert_eq!("1,234.5\t25", tabs.format_transformed(1234.5, 25., "%"));
+    }
+
+    #[test]
+    fn test_output_delimiter() {
+        let fields = vec!["a".to_string(), "12".to_string()];
+        assert_eq!("a 12", formatter(None).join(&fields));
+        let tabs = OutputFormatter { delimiter: Some('\t'), ..formatter(None) };
+        assert_eq!("a\t12", tabs.join(&fields));
+        let csv = OutputFormatter { csv: true, ..formatter(None) };
+        assert_eq!("1,25", csv.format_transformed(1., 25., "%"));
+        let tabs = OutputFormatter { csv: true, ..tabs };
+        assert_eq!("1\t25", tabs.format_transformed(1., 25., "%"));
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let rows = vec![
+            vec!["apples".to_string(), "4".to_string()],
+            vec!["b".to_string(), "1,234.5".to_string()],
+        ];
+        let expected = vec![
+            "key    | result",
+            "-------+--------",
+            "apples | 4",
+            "b      | 1,234.5",
+        ];
+        assert_eq!(expected, table(&["key", "result"], &rows));
+    }
+
+    #[test]
+    fn test_table_header_widest() {
+        let rows = vec![vec!["a".to_string(), "1".to_string()]];
+        assert_eq!(vec!["key | result", "----+-------", "a   | 1"], table(&["key", "result"], &rows));
+    }
+
+    #[test]
+    fn test_precision() {
+        assert_eq!("3.300", formatter(None).with_precision(3).format(3.3));
+        assert_eq!("3", formatter(None).with_precision(0).format(3.3));
+        assert_eq!("1,234.50", formatter(Some(',')).with_precision(2).format(1234.5));
+    }
+
+    #[test]
+    fn test_duration_output() {
+        let formatter = OutputFormatter { duration: Some(DurationUnit::Millis), ..formatter(Some(',')) };
+        assert_eq!("1m 23s", formatter.format(83000.));
+    }
+
+    #[test]
+    fn test_sign_always() {
+        let signed = OutputFormatter { sign_always: true, ..formatter(None) };
+        assert_eq!("+5.5", signed.format(5.5));
+        assert_eq!("-5.5", signed.format(-5.5));
+        assert_eq!("+0", signed.format(0.));
+        assert_eq!("+1.50", signed.with_precision(2).format(1.5));
+        let grouped = OutputFormatter { sign_always: true, ..formatter(Some(',')) };
+        assert_eq!("+1,234", grouped.format(1234.));
+    }
+
+    #[test]
+    fn test_align() {
+        let steps = vec!["1000 - 3 = 997".to_string(), "997 - 250.5 = 746.5".to_string(), "746.5 - 1 = 745.5".to_string()];
+        assert_eq!(vec![
+            " 1000 -     3 =   997",
+            "  997 - 250.5 = 746.5",
+            "746.5 -     1 = 745.5",
+        ], align(&steps));
+        assert!(align(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(Ok("0xff".to_string()), hex(255.));
+        assert_eq!(Ok("0x0".to_string()), hex(0.));
+        assert_eq!(Ok("-0x10".to_string()), hex(-16.));
+        assert_eq!(Err("Can't print 2.5 in hex, it isn't a whole number".to_string()), hex(2.5));
+        assert!(hex(f64::INFINITY).is_err());
+        assert_eq!(Err("Can't print 1000000000000000000000000000000 in hex, it's out of range for hex output".to_string()), hex(1e30));
+        assert!(hex(1e300).is_err());
+    }
+
+    #[test]
+    fn test_ratio() {
+        let formatter = formatter(None);
+        assert_eq!(Ok("3:2".to_string()), formatter.ratio(6., 4., false));
+        assert_eq!(Ok("3:2 (1.5)".to_string()), formatter.ratio(6., 4., true));
+        assert_eq!(Ok("-1:3".to_string()), formatter.ratio(2., -6., false));
+        assert_eq!(Ok("0:1".to_string()), formatter.ratio(0., 5., false));
+        assert_eq!(Ok("0.375:1".to_string()), formatter.ratio(1.5, 4., false));
+        assert!(formatter.ratio(1., 0., false).is_err());
+    }
+
+    #[test]
+    fn test_output_locale() {
+        let german = OutputFormatter { locale: Some("de_DE".parse().unwrap()), ..formatter(None) };
+        assert_eq!("1.234.567", german.format(1234567.));
+        assert_eq!("1.234,5", german.format(1234.5));
+        assert_eq!("-1.234,50", german.with_precision(2).format(-1234.5));
+        assert_eq!("0,25", german.format(0.25));
+        let french = OutputFormatter { locale: Some("fr".parse().unwrap()), ..formatter(None) };
+        assert_eq!("65 536,25", french.format(65536.25));
+        assert_eq!("NaN", french.format(f64::NAN));
+        assert_eq!(Ok(Locale { decimal: '.', group: ',' }), "en-US.UTF-8".parse());
+        assert!("xx".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn test_read_locale() {
+        let french: Locale = "fr".parse().unwrap();
+        assert_eq!("1234.56", french.read("1 234,56"));
+        assert_eq!("1234.56", french.read("1\u{202f}234,56"));
+        let german: Locale = "de".parse().unwrap();
+        assert_eq!("-1234567.5", german.read("-1.234.567,5"));
+        assert_eq!("1234.5", "en".parse::<Locale>().unwrap().read("1,234.5"));
+    }
+
+    #[test]
+    fn test_also_decimal_and_scientific() {
+        let formatter = formatter(Some(','));
+        assert_eq!(Ok("1,234.5".to_string()), formatter.also(1234.5, Also::Decimal));
+        assert_eq!(Ok("1.2345e3".to_string()), formatter.also(1234.5, Also::Scientific));
+        assert_eq!(Ok("0xff".to_string()), formatter.also(255., Also::Hex));
+        assert_eq!(Ok(Also::Scientific), "scientific".parse());
+        assert!("binary".parse::<Also>().is_err());
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(Ok("3/8".to_string()), fraction("0.375"));
+        assert_eq!(Ok("-5/2".to_string()), fraction("-2.5"));
+        assert_eq!(Ok("7/1".to_string()), fraction("7"));
+        assert!(fraction("inf").is_err());
+        assert!(fraction("NaN").is_err());
+        assert!(fraction("0.12345678901234567890123").is_err());
+    }
+
+    #[test]
+    fn test_coerce_int() {
+        let coerced = OutputFormatter { coerce_int: true, ..formatter(None) }.with_precision(2);
+        assert_eq!("9", coerced.format(9.));
+        assert_eq!("9.50", coerced.format(9.5));
+        assert_eq!("-1,000", OutputFormatter { thousands_separator: Some(','), ..coerced }.format(-1000.));
+        assert_eq!("inf", coerced.format(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_engineering() {
+        let engineering = OutputFormatter { engineering: true, ..formatter(None) };
+        assert_eq!("12.3e3", engineering.format(12300.));
+        assert_eq!("1.5e-3", engineering.format(0.0015));
+        assert_eq!("150e-9", engineering.format(0.00000015));
+        assert_eq!("-999e0", engineering.format(-999.));
+        assert_eq!("1e6", engineering.format(1e6));
+        assert_eq!("0e0", engineering.format(0.));
+        assert_eq!("inf", engineering.format(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_engineering_precision() {
+        let engineering = OutputFormatter { engineering: true, ..formatter(None) }.with_precision(2);
+        assert_eq!("12.35e3", engineering.format(12345.));
+        assert_eq!("1.00e6", engineering.format(999999.));
+        assert_eq!("-4.00e-6", engineering.format(-0.000004));
+    }
+
+    #[test]
+    fn test_template() {
+        let template: Template = "Total: {result} over {count} items ({min} to {max}, {operation}), {result}".parse().unwrap();
+        let values = [("result", "6".to_string()), ("count", "3".to_string()), ("operation", "add".to_string()),
+            ("min", "1".to_string()), ("max", "3".to_string())];
+        assert_eq!("Total: 6 over 3 items (1 to 3, add), 6", template.fill(&values));
+        assert_eq!("no placeholders", "no placeholders".parse::<Template>().unwrap().fill(&values));
+        assert!("{total}".parse::<Template>().is_err());
+        assert!("{result".parse::<Template>().is_err());
+    }
+}
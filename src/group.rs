@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+/// Apply `operator` separately to the values of each key, returning the results ordered by key.
+/// Each key starts from its first value, or from `start` combined with it when given.
+pub fn group_by(values: impl Iterator<Item=(String, f64)>, operator: fn(f64, f64) -> f64, start: Option<f64>) -> Vec<(String, f64)> {
+    let mut groups: HashMap<String, f64> = HashMap::new();
+    for (key, val) in values {
+        match groups.entry(key) {
+            Entry::Occupied(mut e) => {
+                let acc = e.get_mut();
+                *acc = operator(*acc, val);
+            },
+            Entry::Vacant(e) => {
+                e.insert(start.map_or(val, |s| operator(s, val)));
+            }
+        }
+    }
+    let mut groups: Vec<(String, f64)> = groups.into_iter().collect();
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+    groups
+}
+
+/// Like `group_by` for values already sorted by key, yielding each key's result as soon as the
+/// next key starts instead of holding every group. A key sorting before the one it follows stops
+/// the groups with an error, as the input isn't sorted.
+pub fn group_sorted(values: impl Iterator<Item=(String, f64)>, operator: fn(f64, f64) -> f64, start: Option<f64>) -> impl Iterator<Item=Result<(String, f64), String>> {
+    let mut values = values.peekable();
+    let mut previous: Option<String> = None;
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None
+        }
+        let (key, val) = values.next()?;
+        if let Some(previous) = previous.as_ref().filter(|previous| key < **previous) {
+            failed = true;
+            return Some(Err(format!("Key {} comes after {}, the input isn't sorted by key", key, previous)))
+        }
+        let mut acc = start.map_or(val, |s| operator(s, val));
+        while let Some((next, _)) = values.peek() {
+            if *next != key {
+                break
+            }
+            let (_, val) = values.next().expect("peeked");
+            acc = operator(acc, val);
+        }
+        previous = Some(key.clone());
+        Some(Ok((key, acc)))
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{group_by, group_sorted};
+
+    fn values(pairs: &[(&str, f64)]) -> impl Iterator<Item=(String, f64)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn test_group_two_keys() {
+        let groups = group_by(values(&[("b", 1.), ("a", 2.), ("b", 3.), ("a", 4.)]), std::ops::Add::add, None);
+        assert_eq!(vec![("a".to_string(), 6.), ("b".to_string(), 4.)], groups);
+    }
+
+    #[test]
+    fn test_group_order_dependent_operation() {
+        let groups = group_by(values(&[("x", 10.), ("y", 1.), ("x", 3.)]), std::ops::Sub::sub, None);
+        assert_eq!(vec![("x".to_string(), 7.), ("y".to_string(), 1.)], groups);
+        let groups = group_by(values(&[("x", 10.), ("y", 1.), ("x", 3.)]), std::ops::Sub::sub, Some(0.));
+        assert_eq!(vec![("x".to_string(), -13.), ("y".to_string(), -1.)], groups);
+    }
+
+    #[test]
+    fn test_group_sorted() {
+        let groups: Result<Vec<(String, f64)>, String> = group_sorted(values(&[("a", 1.), ("a", 2.), ("b", 10.), ("c", 3.), ("c", 4.)]), std::ops::Add::add, None).collect();
+        assert_eq!(Ok(vec![("a".to_string(), 3.), ("b".to_string(), 10.), ("c".to_string(), 7.)]), groups);
+        let groups: Result<Vec<(String, f64)>, String> = group_sorted(values(&[("x", 10.), ("x", 3.), ("y", 1.)]), std::ops::Sub::sub, Some(0.)).collect();
+        assert_eq!(Ok(vec![("x".to_string(), -13.), ("y".to_string(), -1.)]), groups);
+        assert_eq!(0, group_sorted(values(&[]), std::ops::Add::add, None).count());
+    }
+
+    #[test]
+    fn test_group_sorted_streams() {
+        // the first group comes out before the rest of the input is read
+        let mut read = 0;
+        let input = values(&[("a", 1.), ("a", 2.), ("b", 3.), ("b", 4.)]).inspect(|_| read += 1);
+        let first = group_sorted(input, std::ops::Add::add, None).next();
+        assert_eq!(Some(Ok(("a".to_string(), 3.))), first);
+        assert_eq!(3, read);
+    }
+
+    #[test]
+    fn test_group_sorted_unsorted() {
+        let groups: Vec<Result<(String, f64), String>> = group_sorted(values(&[("b", 1.), ("a", 2.), ("c", 3.)]), std::ops::Add::add, None).collect();
+        assert_eq!(2, groups.len());
+        assert_eq!(Ok(("b".to_string(), 1.)), groups[0]);
+        assert!(groups[1].is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The columns of a --history-file, written as its first row.
+const HEADER: [&str; 3] = ["timestamp", "operation", "result"];
+
+/// `time` as it's written in the timestamp column, like 2024-03-01T12:00:00Z.
+pub fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Append a row to the CSV file at `path`, creating it with a header first if it's new or empty.
+/// The file is locked while it's written and the row goes in a single write, so runs appending
+/// at the same time don't mix up their rows.
+pub fn append(path: &Path, timestamp: &str, operation: &str, result: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.lock()?;
+    let mut rows = csv::Writer::from_writer(Vec::new());
+    if file.metadata()?.len() == 0 {
+        rows.write_record(HEADER)?;
+    }
+    rows.write_record([timestamp, operation, result])?;
+    let rows = rows.into_inner().map_err(|e| e.into_error())?;
+    file.write_all(&rows)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{append, timestamp};
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_rows_are_appended() {
+        let path = std::env::temp_dir().join("mathcli_test_history.csv");
+        let _ = fs::remove_file(&path);
+        append(&path, "2024-03-01T12:00:00Z", "add", "6").unwrap();
+        append(&path, "2024-03-02T12:00:00Z", "add", "1,234.5").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!("timestamp,operation,result\n2024-03-01T12:00:00Z,add,6\n2024-03-02T12:00:00Z,add,\"1,234.5\"\n", contents);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_appends() {
+        let path = std::env::temp_dir().join("mathcli_test_history_concurrent.csv");
+        let _ = fs::remove_file(&path);
+        let writers: Vec<_> = (0..8).map(|n| {
+            let path = path.clone();
+            std::thread::spawn(move || append(&path, "2024-03-01T12:00:00Z", "add", &n.to_string()).unwrap())
+        }).collect();
+        writers.into_iter().for_each(|writer| writer.join().unwrap());
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(9, lines.len());
+        assert_eq!("timestamp,operation,result", lines[0]);
+        assert!(lines[1..].iter().all(|line| line.starts_with("2024-03-01T12:00:00Z,add,")));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!("2024-03-01T12:00:00Z", timestamp(UNIX_EPOCH + Duration::from_secs(1709294400)));
+    }
+}
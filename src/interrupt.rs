@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// The result so far, shared with the Ctrl-C handler so an interrupted run can still print it.
+#[derive(Default)]
+pub struct Progress {
+    result: AtomicU64,
+    started: AtomicBool,
+}
+
+impl Progress {
+    fn record(&self, result: f64) {
+        self.result.store(result.to_bits(), Ordering::SeqCst);
+        self.started.store(true, Ordering::SeqCst);
+    }
+
+    /// The latest result, `None` before the first value.
+    pub fn get(&self) -> Option<f64> {
+        match self.started.load(Ordering::SeqCst) {
+            true => Some(f64::from_bits(self.result.load(Ordering::SeqCst))),
+            false => None
+        }
+    }
+}
+
+/// Fold like `Iterator::fold`/`reduce`, recording every intermediate result in `progress`.
+pub fn fold(
+    values: impl Iterator<Item=f64>,
+    operator: fn(f64, f64) -> f64,
+    start: Option<f64>,
+    progress: &Progress,
+) -> Option<f64> {
+    if let Some(start) = start {
+        progress.record(start);
+    }
+    values.fold(start, |acc, val| {
+        let result = acc.map_or(val, |acc| operator(acc, val));
+        progress.record(result);
+        Some(result)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Progress, fold};
+
+    #[test]
+    fn test_progress_mid_fold() {
+        let progress = Progress::default();
+        assert_eq!(None, progress.get());
+        let mut seen = Vec::new();
+        // look at the progress before each value is folded in, like a handler interrupting there
+        let values = vec![10., 3., 2.].into_iter().inspect(|_| seen.push(progress.get()));
+        let result = fold(values, std::ops::Sub::sub, None, &progress);
+        assert_eq!(Some(5.), result);
+        assert_eq!(vec![None, Some(10.), Some(7.)], seen);
+        assert_eq!(Some(5.), progress.get());
+    }
+
+    #[test]
+    fn test_progress_from_start() {
+        let progress = Progress::default();
+        assert_eq!(None, fold(std::iter::empty(), std::ops::Mul::mul, None, &progress));
+        assert_eq!(None, progress.get());
+        assert_eq!(Some(1.), fold(std::iter::empty(), std::ops::Mul::mul, Some(1.), &progress));
+        assert_eq!(Some(1.), progress.get());
+    }
+}
@@ -0,0 +1,105 @@
+/// The elements of a flat JSON array, e.g. `[1, 2.5, -3]`, as their text. The elements aren't
+/// checked to be numbers, that's left to parsing them like any other value; nested arrays and
+/// objects are an error.
+pub fn elements(text: &str) -> Result<Vec<String>, String> {
+    let inner = text.trim()
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| "Input is not a JSON array".to_string())?;
+    if inner.trim().is_empty() {
+        return Ok(Vec::new())
+    }
+    let mut elements = Vec::new();
+    let mut element = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in inner.chars() {
+        if in_string {
+            element.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => ()
+            }
+            continue
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                element.push(c);
+            },
+            '[' | '{' => return Err(format!("Nested value at element {} of the JSON array", elements.len() + 1)),
+            ']' | '}' => return Err(format!("Unexpected {} in the JSON array", c)),
+            ',' => elements.push(finish(&mut element, elements.len())?),
+            _ => element.push(c)
+        }
+    }
+    if in_string {
+        return Err("Unterminated string in the JSON array".to_string())
+    }
+    elements.push(finish(&mut element, elements.len())?);
+    Ok(elements)
+}
+
+/// Take the element read so far, which must not be blank.
+fn finish(element: &mut String, index: usize) -> Result<String, String> {
+    let text = element.trim().to_string();
+    element.clear();
+    match text.is_empty() {
+        true => Err(format!("Missing element {} of the JSON array", index + 1)),
+        false => Ok(text)
+    }
+}
+
+/// `s` as a JSON string, in quotes and with the characters JSON doesn't allow escaped.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{elements, quote};
+
+    #[test]
+    fn test_flat_array() {
+        assert_eq!(Ok(vec!["1".to_string(), "2.5".to_string(), "-3e2".to_string()]), elements(" [1, 2.5,\n-3e2]\n"));
+        assert_eq!(Ok(Vec::new()), elements("[ ]"));
+    }
+
+    #[test]
+    fn test_non_numeric_elements_are_kept() {
+        assert_eq!(Ok(vec!["\"a, [b]\"".to_string(), "null".to_string()]), elements(r#"["a, [b]", null]"#));
+    }
+
+    #[test]
+    fn test_malformed_arrays() {
+        assert!(elements("1, 2").is_err());
+        assert!(elements("[1, [2, 3]]").is_err());
+        assert!(elements("[1, {\"a\": 2}]").is_err());
+        assert!(elements("[1,, 2]").is_err());
+        assert!(elements("[1, 2,]").is_err());
+        assert!(elements("[\"1]").is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(r#""add""#, quote("add"));
+        assert_eq!(r#""a \"b\"\\c\nd\u0001""#, quote("a \"b\"\\c\nd\u{1}"));
+    }
+}
@@ -1,7 +1,39 @@
-#![feature(iter_map_while, iterator_fold_self, exclusive_range_pattern)]
-use clap::Clap;
+use clap::{Clap, IntoApp};
+use encoding_rs::Encoding;
+use float::FloatBits;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod cache;
+mod checkpoint;
+mod duration;
+mod exec;
+mod float;
+mod format;
+mod group;
+mod history;
+mod interrupt;
+mod json;
+mod merge;
+mod monotonic;
+mod profile;
+mod sample;
+mod system_log;
+mod timestamp;
+mod transform;
+mod wait;
+mod window;
 
 
 /// Apply a mathmatical operation to a stream of inputs.
@@ -15,13 +47,20 @@ use std::io::prelude::*;
 /// $ 2.3333333
 /// $ printf '5\n4\n\n' | mathcli add
 /// $ 9
+/// $ mathcli --file a.txt --file b.txt --merge-sorted add
+/// $ MATHCLI_OP=add mathcli --file a.txt
+/// $ printf 'a 1\nb 2\na 3\n\n' | mathcli --group-by 1 --column 2 add
+/// $ a 4
+/// $ b 2
 /// ```
 #[derive(Clap)]
 #[clap(version = "0.1", author = "Mike A. <michael.alvarino@gmail.com>")]
+#[clap(group = clap::ArgGroup::with_name("fields").multiple(true))]
 struct Opts {
-    /// Options are add, sub, mul, div
+    /// Options are add, sub, mul, div, count-nonzero, count-matching, sum-of-squares, trimmed-mean,
+    /// count-distinct. Read from MATHCLI_OP when not given
     #[clap(subcommand)]
-    subcmd: SubCommand,
+    subcmd: Option<SubCommand>,
     /// Use the identity for this operation as a starting point
     #[clap(long)]
     identity_starting_point: bool,
@@ -29,16 +68,403 @@ struct Opts {
     /// if a parse failure does occur
     #[clap(short, long)]
     silent: bool,
-    /// Ignore lines at the beginning of input.
+    /// Ignore lines at the beginning of input. The operation takes them as its identity, every
+    /// other mode leaves them out, as do the counts of values read.
     #[clap(short, long, default_value="0")]
     ignore: usize,
-    /// Logging verbosity, all logs go to stderr. Number of v's translates to logging level
+    /// Fail rather than warn when an empty line turns up among the --ignore lines, which
+    /// means the data probably started before the count ran out, or when a line doesn't have the
+    /// --expect-tokens
+    #[clap(long)]
+    strict: bool,
+    /// Logging verbosity, logs go to stderr unless --syslog. Number of v's translates to logging level
     #[clap(short, long, parse(from_occurrences))]
     verbose: usize,
+    /// Write the values in log messages with this many decimal places, rather than in full
+    #[clap(long)]
+    log_precision: Option<usize>,
+    /// Send the logs to syslog instead of stderr, and the result there as well as to stdout
+    #[clap(long)]
+    syslog: bool,
+    /// The syslog facility for --syslog, like user, daemon or local0
+    #[clap(long, default_value="user", parse(try_from_str = system_log::facility))]
+    syslog_facility: syslog::Facility,
+    /// The name --syslog tags messages with
+    #[clap(long, default_value="mathcli")]
+    syslog_tag: String,
+    /// Read input from these files instead of stdin. Files are read one after another
+    #[clap(short, long, number_of_values = 1, parse(from_os_str))]
+    file: Vec<PathBuf>,
+    /// Read stdin as well as the files, before them
+    #[clap(long, conflicts_with("files-first"))]
+    stdin_first: bool,
+    /// Read stdin as well as the files, after them
+    #[clap(long)]
+    files_first: bool,
+    /// Read the files matching this glob pattern, in sorted order, after any --file
+    #[clap(long)]
+    input_glob: Option<String>,
+    /// Read the output of this shell command, after the files. If the command fails, so does the run
+    #[clap(long, number_of_values = 1)]
+    exec: Vec<String>,
+    /// Treat each file as already sorted and merge them into a single sorted stream.
+    /// Each file is cleaned on its own, so --ignore applies to every file
+    #[clap(long)]
+    merge_sorted: bool,
+    /// Print the merged stream, one value per line, instead of applying the operation
+    #[clap(long, requires("merge-sorted"))]
+    emit_merged: bool,
+    /// Apply the operation to each input on its own, then combine the results with --merge-op.
+    /// --ignore and the starting point apply to every input
+    #[clap(long, conflicts_with_all(&["merge-sorted", "group-by"]))]
+    per_file: bool,
+    /// The operation combining the results of --per-file: add, sub, mul or div. Defaults to the
+    /// operation itself
+    #[clap(long, requires("per-file"))]
+    merge_op: Option<SubCommand>,
+    /// Combine the results of --per-file as the mean of each input's result over its values,
+    /// weighted by its number of values. With add, the mean of the inputs' means
+    #[clap(long, requires("per-file"), conflicts_with("merge-op"))]
+    weighted_by_line_count: bool,
+    /// Read the whole input as one JSON array of numbers, e.g. `[1, 2, 3]`, instead of a value per
+    /// line. Elements that aren't numbers go through --silent like bad lines; nested arrays are
+    /// an error
+    #[clap(long, conflicts_with_all(&["merge-sorted", "per-file", "group-by", "file-subtotals"]))]
+    json_array: bool,
+    /// Group the digits of the result's integer part, e.g. 1,234,567
+    #[clap(long)]
+    format_thousands: bool,
+    /// Separator used by --format-thousands
+    #[clap(long, default_value=",")]
+    thousands_separator: char,
+    /// Write the result with the decimal point and digit grouping of this locale, e.g. de for
+    /// 1.234,5. Only the language is looked at
+    #[clap(long, conflicts_with_all(&["format-thousands", "output-hex"]))]
+    output_locale: Option<format::Locale>,
+    /// Read numbers written with the decimal point and digit grouping of this locale, e.g. fr for
+    /// 1 234,56. Lines are split into columns before the grouping is read, so grouping spaces in a
+    /// column need --space-grouped
+    #[clap(long, conflicts_with("input-as-duration"))]
+    input_locale: Option<format::Locale>,
+    /// Print whole results without any decimal places, even where a precision is set, like 9
+    /// rather than 9.00. Other results are written as usual
+    #[clap(long, alias = "trim-trailing-zeros")]
+    coerce_int: bool,
+    /// Always print the sign of the result, + for positive results and 0
+    #[clap(long)]
+    output_sign_always: bool,
+    /// Print the result in hexadecimal, like 0xff. Results that aren't whole numbers are an error
+    #[clap(long, conflicts_with_all(&["format-thousands", "output-sign-always", "output-as-duration", "match-input-precision"]))]
+    output_hex: bool,
+    /// Print the result in engineering notation, with an exponent that's a multiple of 3, like
+    /// 12.3e3. --match-input-precision sets the decimal places of the number before the exponent
+    #[clap(long, conflicts_with_all(&["format-thousands", "output-as-duration", "output-hex", "output-locale"]))]
+    engineering: bool,
+    /// Print the result as a ratio to this reference, like 3:2 for a result of 6 and a reference
+    /// of 4. Whole numbers are reduced to lowest terms
+    #[clap(long, allow_hyphen_values = true, conflicts_with("output-hex"))]
+    output_ratio: Option<f64>,
+    /// Also print the result in this format on a line of its own after it: decimal, scientific,
+    /// hex for whole numbers or fraction. Can be given more than once
+    #[clap(long, number_of_values = 1)]
+    also: Vec<format::Also>,
+    /// End every line printed to stdout with lf or crlf, for tools on Windows
+    #[clap(long, default_value = "lf")]
+    line_ending: format::LineEnding,
+    /// Follow the --output-ratio with the ratio as a single number, like 3:2 (1.5)
+    #[clap(long, requires("output-ratio"))]
+    ratio_decimal: bool,
+    /// Flip the sign of every parsed value before applying the operation.
+    /// Ignored lines and parse failures under --silent still use the identity
+    #[clap(long)]
+    negate: bool,
+    /// Replace every parsed value with 1/x before applying the operation, after --negate.
+    /// `add --reciprocal` gives the harmonic sum. A 0 is an error, or the identity under --silent
+    #[clap(long)]
+    reciprocal: bool,
+    /// Replace every parsed value with its natural log, after --reciprocal. `add --log` sums
+    /// the logs, which is the log of the product. Values that aren't positive are an error,
+    /// or the identity under --silent
+    #[clap(long)]
+    log: bool,
+    /// Replace every parsed value x with e^x, after --log. Turns log space data back into linear
+    #[clap(long)]
+    exp: bool,
+    /// Round every parsed value to the nearest multiple of this, after --exp. Handy for
+    /// bucketing, e.g. latencies to the nearest 10ms
+    #[clap(long, parse(try_from_str = multiple))]
+    round_to_multiple: Option<f64>,
+    /// Take the value from this whitespace separated column of each line, starting at 1. See --csv
+    #[clap(short, long, group = "fields")]
+    column: Option<usize>,
+    /// Read a timestamp from this whitespace separated column of each line, in seconds since the
+    /// epoch or ISO 8601 like 2024-03-01T12:00:00Z. The span from the earliest to the latest is
+    /// the duration of rate. Invalid timestamps are an error, or skipped under --silent
+    #[clap(long)]
+    timestamp_column: Option<usize>,
+    /// Split lines into columns as comma separated values rather than on whitespace. Quoted fields
+    /// may hold commas, and quoted numbers may group their digits with them, like "1,234"
+    #[clap(long, requires("fields"))]
+    csv: bool,
+    /// Drop the empty fields of --csv lines, so `1,,2` has the two fields 1 and 2 and later columns
+    /// move up. A line of nothing but empty fields isn't a blank line and doesn't stop the input
+    #[clap(long, requires("csv"))]
+    skip_empty_tokens: bool,
+    /// Take a value from each of this range of whitespace separated columns of every line, e.g.
+    /// 2-5. Lines missing some of them are an error, or under --silent give just the ones they have
+    #[clap(long, parse(try_from_str = column_range), conflicts_with_all(&["column", "group-by"]))]
+    columns: Option<(usize, usize)>,
+    /// Read the lines as the rows of a matrix and print the result of each of its columns, one
+    /// per line. Columns are whitespace separated, or comma separated with --csv. The whole input
+    /// is held in memory. Rows with fewer columns than the widest are an error, see --transpose-pad
+    #[clap(long, group = "fields", conflicts_with_all(&["column", "columns", "group-by", "per-file", "merge-sorted", "json-array", "multiplier-column"]))]
+    transpose: bool,
+    /// Print how many tokens each line splits into instead of applying the operation, e.g. to
+    /// check fixed width records. Lines are split on whitespace, or as comma separated with --csv
+    #[clap(long, group = "fields", conflicts_with_all(&["transpose", "group-by", "per-file", "merge-sorted", "json-array"]))]
+    count_tokens: bool,
+    /// Warn about every line that doesn't split into this many tokens, like --count-tokens counts
+    /// them, or fail with --strict
+    #[clap(long, group = "fields")]
+    expect_tokens: Option<usize>,
+    /// Apply the operation to each column of the lines on its own as they're read, and print the
+    /// results on one line, separated by the --output-delimiter. Lines with a different number of
+    /// columns than the first are an error, or under --silent add to the columns they have
+    #[clap(long, group = "fields", conflicts_with_all(&["column", "columns", "transpose", "count-tokens", "group-by", "per-file", "merge-sorted", "json-array", "multiplier-column"]))]
+    per_field: bool,
+    /// Fill the columns missing from short --transpose rows with this value
+    #[clap(long, allow_hyphen_values = true, requires("transpose"))]
+    transpose_pad: Option<f64>,
+    /// Multiply each value by the number in this column of its line, e.g. a price by a quantity
+    #[clap(long, requires("column"))]
+    multiplier_column: Option<usize>,
+    /// Apply the operation separately for each distinct key in this column and print
+    /// `key result` pairs sorted by key. Use with --column to pick the value
+    #[clap(short, long, requires("column"), conflicts_with("merge-sorted"))]
+    group_by: Option<usize>,
+    /// Take the input of --group-by to be sorted by key and print each group's result as soon as
+    /// its key ends, rather than holding all the groups and sorting them
+    #[clap(long, requires("group-by"), conflicts_with("format-table"))]
+    grouped_sorted: bool,
+    /// Print each value as a percentage of the sum of all values instead of applying the
+    /// operation. The whole input is held in memory
+    #[clap(long)]
+    percent_of_total: bool,
+    /// Print the softmax of the values instead of applying the operation: e to the power of each,
+    /// divided by the sum of them all, so they add up to 1. The whole input is held in memory.
+    #[clap(long, conflicts_with_all(&["percent-of-total", "pct-change"]))]
+    softmax: bool,
+    /// Print the percent change from each value to the next instead of applying the operation,
+    /// one line fewer than there are values
+    #[clap(long, conflicts_with("percent-of-total"))]
+    pct_change: bool,
+    /// Print `original,transformed` lines from transforms like --percent-of-total. Numbers
+    /// holding the delimiter, like 1,234.5 with --format-thousands, are quoted
+    #[clap(long)]
+    csv_output: bool,
+    /// Start the operation from the number in this file, e.g. yesterday's total
+    #[clap(long, parse(from_os_str), conflicts_with("identity-starting-point"))]
+    seed_from_file: Option<PathBuf>,
+    /// Append a row with the time, the operation and the result of every run to this CSV file,
+    /// creating it with a `timestamp,operation,result` header, to build a time series
+    #[clap(long)]
+    history_file: Option<PathBuf>,
+    /// Save results in this directory, under a hash of the operation, the options that change the
+    /// result and the contents of the input files, and print the saved result instead of reading
+    /// the inputs again when none of them has changed. Only a single result of the operation is
+    /// saved, and only when every input is a file
+    #[clap(long)]
+    cache_dir: Option<PathBuf>,
+    /// Periodically save the running result and how many values it covers to this file
+    #[clap(long, parse(from_os_str), conflicts_with_all(&["group-by", "percent-of-total", "emit-merged"]))]
+    checkpoint_file: Option<PathBuf>,
+    /// Save a checkpoint to --checkpoint-file after this many values, 0 only saves at the end
+    #[clap(long, default_value="1000")]
+    checkpoint_every: usize,
+    /// Continue from the checkpoint file if there is one, skipping the values it already covers
+    #[clap(long, requires("checkpoint-file"))]
+    resume: bool,
+    /// Print multi-row results, like --group-by, as an aligned table with headers
+    #[clap(long)]
+    format_table: bool,
+    /// Separate the fields of multi-field output, like --group-by or --csv-output, with this
+    /// character. tab, comma and space name those. Defaults to a space, or a comma for --csv-output
+    #[clap(long, parse(try_from_str = delimiter), conflicts_with("format-table"))]
+    output_delimiter: Option<char>,
+    /// Instead of the result, print the --window-stat of the last this many values for every
+    /// value. Until the window fills up it covers the values so far
+    #[clap(long, parse(try_from_str = window_size), conflicts_with_all(&["percent-of-total", "group-by"]))]
+    running_window_stats: Option<usize>,
+    /// The statistic for --running-window-stats: mean, min or max
+    #[clap(long, default_value="mean")]
+    window_stat: window::Stat,
+    /// Character encoding of the input, e.g. latin1 or windows-1252. Lines that aren't valid in
+    /// it stop the stream, or are decoded with replacement characters under --silent
+    #[clap(long, default_value="utf-8", parse(try_from_str = encoding))]
+    input_encoding: &'static Encoding,
+    /// Print results with as many decimal places as the most precise valid input value
+    #[clap(long)]
+    match_input_precision: bool,
+    /// Keep going past lines that fail to parse, leaving them out of the operation, then
+    /// report how many there were and exit with an error if there were any
+    #[clap(long, conflicts_with_all(&["silent", "group-by"]))]
+    collect_errors: bool,
+    /// Print the result as a duration like 1m 23s, taking it to be in this unit: ms or s
+    #[clap(long)]
+    output_as_duration: Option<duration::DurationUnit>,
+    /// Read values as durations like 1h, 1m30s or 250ms, in seconds. Plain numbers are seconds
+    #[clap(long)]
+    input_as_duration: bool,
+    /// Reject values written with an exponent, like 1e3, as parse errors, for data that should only
+    /// hold plain decimals
+    #[clap(long)]
+    no_scientific_input: bool,
+    /// Match the units of --input-as-duration in any case, like 1M30S or 250Ms. --bool-as-number
+    /// is never case sensitive, and numbers are read as they are
+    #[clap(long, requires("input-as-duration"))]
+    ignore_case: bool,
+    /// Exit with an error instead of printing a result when fewer than this many values were read.
+    /// Lines skipped by --ignore don't count
+    #[clap(long, default_value="0")]
+    min_values: usize,
+    /// Exit with an error once more than this many values are held in memory at once, by the
+    /// modes that need all of them together like --percent-of-total, trimmed-mean or --transpose
+    #[clap(long)]
+    max_buffer_count: Option<usize>,
+    /// Print every step of the operation to stderr, e.g. `10 - 3 = 7`
+    #[clap(long, conflicts_with("checkpoint-file"))]
+    show_steps: bool,
+    /// On Ctrl-C, print the result of the values read so far, before any --then, and exit
+    #[clap(long, conflicts_with_all(&["checkpoint-file", "show-steps"]))]
+    signal_handling: bool,
+    /// Add up with Kahan-Babuška compensated summation, which keeps the rounding error of long
+    /// sums from growing with every value. Only applies to additive operations, like add, rate and
+    /// trimmed-mean
+    #[clap(long, conflicts_with_all(&["checkpoint-file", "show-steps", "signal-handling", "file-subtotals"]))]
+    kahan: bool,
+    /// Keep each value with this probability, from 0 to 1, and leave the rest out. The result is
+    /// then only an estimate, e.g. a sum over a sample has to be divided by the rate
+    #[clap(long, parse(try_from_str = probability), conflicts_with("per-file"))]
+    sample_rate: Option<f64>,
+    /// Leave out values more than this many standard deviations from the mean of all of them.
+    /// The whole input is held in memory to find the mean first
+    #[clap(long, conflicts_with("per-file"))]
+    zscore_filter: Option<f64>,
+    /// Keep only the first value of each run of repeated values, e.g. a sensor reading the same
+    /// thing until it changes. Values seen earlier but not just before are kept, see --epsilon
+    #[clap(long, conflicts_with("per-file"))]
+    dedup_consecutive: bool,
+    /// Seed the random choices of --sample-rate so the same values are kept every run
+    #[clap(long, requires("sample-rate"))]
+    sample_seed: Option<u64>,
+    /// Report the lines, values and bytes read, the most values held in memory at once and the
+    /// time taken to stderr at the end of the run
+    #[clap(long)]
+    profile: bool,
+    /// Report to stderr at the end of the run how many lines were read, how many --ignore skipped,
+    /// how many values were parsed from the rest and how many lines were skipped without giving
+    /// one, like blank lines and the empty line the input stopped at. For finding truncated inputs
+    #[clap(long)]
+    report_lines: bool,
+    /// Print the result so far to stderr at the end of every input, e.g. `a.txt 6`
+    #[clap(long, conflicts_with_all(&["merge-sorted", "per-file", "group-by", "checkpoint-file", "show-steps", "signal-handling"]))]
+    file_subtotals: bool,
+    /// Align the --show-steps output into columns. The steps are held back until the end to find the widths
+    #[clap(long, requires("show-steps"))]
+    align_output: bool,
+    /// Read empty lines as this value instead of stopping at the first one
+    #[clap(long)]
+    empty_as: Option<f64>,
+    /// Don't stop at a blank line straight away when the input is slow to arrive: if more data
+    /// follows within --wait-timeout-ms the blank line is skipped, only a blank line at the end of
+    /// the input or followed by silence stops it
+    #[clap(long, conflicts_with_all(&["merge-sorted", "per-file", "file-subtotals", "json-array"]))]
+    wait_for_data: bool,
+    /// How long --wait-for-data waits for more data after a blank line, in milliseconds
+    #[clap(long, default_value="1000")]
+    wait_timeout_ms: u64,
+    /// Skip lines holding only spaces and tabs instead of stopping at them like at an empty line.
+    /// A line with nothing on it, not even whitespace, still stops the input
+    #[clap(long)]
+    ignore_blank_only_lines_anywhere: bool,
+    /// When a line fails to parse, also log this many lines before and after it
+    #[clap(long, conflicts_with("collect-errors"))]
+    error_context: Option<usize>,
+    /// Abort the whole run at the first NaN or infinite input value, even under --silent
+    #[clap(long)]
+    strict_finite: bool,
+    /// Combine the result with the matching --then-value using this operation, e.g.
+    /// `add --then div --then-value 100`. Repeat both to chain several, applied in order
+    #[clap(long, number_of_values = 1, requires("then-value"), conflicts_with("group-by"))]
+    then: Vec<SubCommand>,
+    /// The value the matching --then operation combines the result with
+    #[clap(long, number_of_values = 1, requires("then"), allow_hyphen_values = true)]
+    then_value: Vec<f64>,
+    /// Exit with an error at the first value out of order: inc for values that never go down, dec
+    /// for values that never go up
+    #[clap(long, conflicts_with_all(&["merge-sorted", "group-by"]))]
+    assert_monotonic: Option<monotonic::Order>,
+    /// Read and combine values as 32 or 64 bit floats. 64 keeps large sums precise
+    #[clap(long, default_value="32")]
+    float_bits: float::FloatBits,
+    /// Don't print the result, for when only the exit code of checks like --assert-monotonic matters
+    #[clap(long)]
+    quiet_result: bool,
+    /// Print the result to stderr as well as stdout, as a plain line after any log output
+    #[clap(long, conflicts_with("quiet-result"))]
+    echo_stderr: bool,
+    /// The line number errors give the first line of input, e.g. where a slice of a bigger file starts
+    #[clap(long, default_value="1")]
+    line_offset: usize,
+    /// Read true/false and yes/no, in any case, as 1 and 0
+    #[clap(long)]
+    bool_as_number: bool,
+    /// After the result, print PASS and exit 0 if it's greater than this, otherwise print FAIL and exit 1
+    #[clap(long, allow_hyphen_values = true, conflicts_with("group-by"))]
+    compare_gt: Option<f64>,
+    /// Like --compare-gt, for results less than this. Give both to check a range
+    #[clap(long, allow_hyphen_values = true, conflicts_with("group-by"))]
+    compare_lt: Option<f64>,
+    /// Let --compare-gt and --compare-lt pass a result within this distance of their bound, e.g.
+    /// for results that rounding left just short of it. --assert-nonzero takes results this close
+    /// to 0 to be 0, and --dedup-consecutive values this close to the one before to repeat it
+    #[clap(long, default_value="0")]
+    epsilon: f64,
+    /// After the result, exit 1 if it's 0, within --epsilon
+    #[clap(long, conflicts_with("group-by"))]
+    assert_nonzero: bool,
+    /// Write the result line from this template, filling in {result}, {count}, {operation}, {min}
+    /// and {max}, e.g. "Total: {result} over {count} items". Lines skipped by --ignore aren't
+    /// among the values counted
+    #[clap(long)]
+    template: Option<format::Template>,
+    /// Exit with the result as the status: its whole part modulo 256, so 300 exits 44 and -1
+    /// exits 255. Results that aren't finite are an error. Errors still exit 1, the same as a
+    /// result of 1
+    #[clap(long, conflicts_with("group-by"))]
+    exit_code_from_result: bool,
+    /// Printed after PASS or FAIL, e.g. the name of the check
+    #[clap(long)]
+    compare_message: Option<String>,
+    /// Remove ANSI escape sequences such as colors from each line before reading it
+    #[clap(long)]
+    strip_ansi: bool,
+    /// Decode each line after --ignore from base64 and read what it holds, e.g. MTIuNQ== for 12.5.
+    /// Lines that aren't valid base64 are an error, or read as they are under --silent
+    #[clap(long)]
+    base64: bool,
+    /// Read digits grouped with spaces, like 1 234 567, as one number. The spaces go before the
+    /// line is split into --columns, so columns of three digit numbers run together
+    #[clap(long)]
+    space_grouped: bool,
+    /// Use this value for lines that fail to parse and carry on, rather than stopping or
+    /// using the identity like --silent
+    #[clap(long, allow_hyphen_values = true, conflicts_with_all(&["silent", "collect-errors"]))]
+    on_error_value: Option<f64>,
 }
 
 /// The set of available sub commands. Standard mathematical operations.
-#[derive(Clap, Clone, Copy)]
+#[derive(Clap, Clone, Debug)]
 enum SubCommand {
     /// Add all inputs.
     /// Identity: 0.0
@@ -48,176 +474,3260 @@ enum SubCommand {
     Sub,
     /// Multiply all inputs.
     /// Identity: 1.0
-    Mul,
+    Mul {
+        /// Leave zeros out of the product instead of letting them make it 0. The result is then
+        /// the product of the other values, 1 if there were only zeros
+        #[clap(long)]
+        skip_zeros: bool,
+    },
     /// Divide all inputs.
     /// Identity: 1.0
     Div,
+    /// Count the inputs that aren't zero.
+    /// Identity: 0.0
+    CountNonzero {
+        /// Count values within this distance of zero as zero
+        #[clap(long, default_value="0")]
+        epsilon: f64,
+    },
+    /// Count the lines matching a regular expression, without reading numbers from them.
+    /// Identity: 0.0
+    CountMatching {
+        /// The regular expression to match lines against
+        pattern: String,
+    },
+    /// Add up the squares of all inputs.
+    /// Identity: 0.0
+    SumOfSquares,
+    /// The mean of the inputs without the highest and lowest `percent` of them. All the values
+    /// are held in memory to sort them.
+    TrimmedMean {
+        /// The percentage of values to drop from each end, from 0 up to but not including 50
+        #[clap(parse(try_from_str = trim_percent))]
+        percent: f64,
+    },
+    /// Count the distinct inputs. All the values are held in memory to sort them.
+    CountDistinct {
+        /// Count values within this distance of each other as the same. Runs of values each
+        /// this close to the next all count once
+        #[clap(long, default_value="0")]
+        epsilon: f64,
+    },
+    /// The first value, without reading any further.
+    First,
+    /// The last value.
+    Last,
+    /// A fingerprint of the values in order, to check whether two streams are the same: the
+    /// 64-bit FNV-1a hash of the bits of every value, in hex.
+    Checksum,
+    /// Add all inputs and divide the total by a duration, e.g. bytes per second.
+    /// Identity: 0.0
+    Rate {
+        /// The time the inputs were measured over, in seconds. Defaults to the span of the
+        /// --timestamp-column
+        #[clap(long, parse(try_from_str = seconds))]
+        duration: Option<f64>,
+    },
+    /// Count the inputs from `low` to `high`, both included unless --exclusive-low or
+    /// --exclusive-high leave them out.
+    /// Identity: 0.0
+    #[clap(setting = clap::AppSettings::AllowNegativeNumbers)]
+    CountBetween {
+        /// The lower bound
+        low: f64,
+        /// The upper bound
+        high: f64,
+        /// Don't count values equal to `low`
+        #[clap(long)]
+        exclusive_low: bool,
+        /// Don't count values equal to `high`
+        #[clap(long)]
+        exclusive_high: bool,
+    },
+    /// Print every operation with its identity and description, one per line separated by tabs
+    #[clap(setting = clap::AppSettings::Hidden)]
+    ListOperations {
+        /// Print a JSON array of objects instead
+        #[clap(long)]
+        json: bool,
+    },
 }
 
-/// Go!
-fn main() {
-    let opts: Opts = Opts::parse();
-    let identity = match opts.subcmd {
-        SubCommand::Mul | SubCommand::Div => 1.,
-        SubCommand::Add | SubCommand::Sub => 0.
-    };
-    // let input_handler = InputHandler::new(&opts, identity);
-    let operator = match opts.subcmd {
-        SubCommand::Add => std::ops::Add::add,
-        SubCommand::Sub => std::ops::Sub::sub,
-        SubCommand::Mul => std::ops::Mul::mul,
-        SubCommand::Div => std::ops::Div::div
-    };
-    stderrlog::new()
-        .verbosity(opts.verbose)
-        .init()
-        .unwrap();
-    log::info!("Starting...");
-    let stdin = io::stdin();
-    let input_handler = InputHandler::new(&opts, identity);
-    let cleaned_input = input_handler.clean_and_enumerate(stdin.lock());
-    let parsed_lines = input_handler.parse_input(cleaned_input);
+impl SubCommand {
+    /// Combines the result so far with the next value, at the width of `bits`.
+    fn operator(&self, bits: FloatBits) -> fn(f64, f64) -> f64 {
+        // an f64 has enough bits that rounding its exact result to an f32 gives the f32 result
+        match (self, bits) {
+            (SubCommand::Sub, FloatBits::Single) => |a, b| (a - b) as f32 as f64,
+            (SubCommand::Sub, FloatBits::Double) => std::ops::Sub::sub,
+            (SubCommand::Mul { .. }, FloatBits::Single) => |a, b| (a * b) as f32 as f64,
+            (SubCommand::Mul { .. }, FloatBits::Double) => std::ops::Mul::mul,
+            (SubCommand::Div, FloatBits::Single) => |a, b| (a / b) as f32 as f64,
+            (SubCommand::Div, FloatBits::Double) => std::ops::Div::div,
+            (SubCommand::First, _) => |a, _| a,
+            (SubCommand::Last, _) => |_, b| b,
+            // counting and squaring add up the values from InputHandler::transform
+            (_, FloatBits::Single) => |a, b| (a + b) as f32 as f64,
+            (_, FloatBits::Double) => std::ops::Add::add
+        }
+    }
 
-    log::info!("Folding...");
-    let result = match opts.identity_starting_point {
-        true => parsed_lines.fold(identity, |acc: f32, x| operator(acc, x)),
-        false => parsed_lines.fold_first(|acc, x| operator(acc, x)).unwrap()
-    };
-    log::info!("Writing result");
-    println!("{}", result);
-}
+    /// The name of the operation on the command line.
+    fn name(&self) -> &'static str {
+        match self {
+            SubCommand::Add => "add",
+            SubCommand::Sub => "sub",
+            SubCommand::Mul { .. } => "mul",
+            SubCommand::Div => "div",
+            SubCommand::CountNonzero { .. } => "count-nonzero",
+            SubCommand::CountMatching { .. } => "count-matching",
+            SubCommand::SumOfSquares => "sum-of-squares",
+            SubCommand::TrimmedMean { .. } => "trimmed-mean",
+            SubCommand::CountDistinct { .. } => "count-distinct",
+            SubCommand::First => "first",
+            SubCommand::Last => "last",
+            SubCommand::Checksum => "checksum",
+            SubCommand::Rate { .. } => "rate",
+            SubCommand::CountBetween { .. } => "count-between",
+            SubCommand::ListOperations { .. } => "list-operations"
+        }
+    }
 
-/// Responsible for cleaning user input
-#[derive(Copy, Clone)]
-struct InputHandler {
-    identity: f32,
-    ignore: usize,
-    silent: bool,
-}
+    /// Whether the operation adds up its values, which --kahan applies to.
+    fn is_additive(&self) -> bool {
+        !matches!(self, SubCommand::Sub | SubCommand::Mul { .. } | SubCommand::Div | SubCommand::First | SubCommand::Last)
+    }
 
-impl InputHandler {
-    /// Use the given identity (0 or 1) and options to configure the handler.
-    pub fn new(opts: &Opts, identity: f32) -> Self {
-        InputHandler {
-            ignore: opts.ignore,
-            silent: opts.silent,
-            identity
+    /// The symbol used to write the operation down.
+    fn symbol(&self) -> char {
+        match self {
+            SubCommand::Sub => '-',
+            SubCommand::Mul { .. } => '*',
+            SubCommand::Div => '/',
+            _ => '+'
         }
     }
+}
 
-    fn clean_and_enumerate<R: BufRead>(self, reader: R) -> impl Iterator<Item=(usize, String)> {
-        reader.lines()
-        // If we fail to read a line due to some io issue, just explode, not useful to continue
-        .map(|x| x.unwrap())
-        // if we don't take ownership, we're referencing data owned by the current function
-        // overhead of creating a String is minimal
-        .map(|x| x.trim().to_string())
-        // gives us (index, value). useful for ignoring lines, logging, etc
-        .enumerate()
+/// Reads one of the plain operations by name, as used for --then.
+impl std::str::FromStr for SubCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "add" => Ok(SubCommand::Add),
+            "sub" => Ok(SubCommand::Sub),
+            "mul" => Ok(SubCommand::Mul { skip_zeros: false }),
+            "div" => Ok(SubCommand::Div),
+            _ => Err(format!("Unknown operation {}, options are add, sub, mul, div", s))
+        }
     }
+}
 
-    /// Reads each value into a float and continues until Err is returned
-    fn parse_input(self, it: impl Iterator<Item=(usize, String)>) -> impl Iterator<Item=f32> {
-        // ignore lines, check for empties, parse to f32, etc
-        it.map(move |(i, val)| self.handle(i, &val))
-        // keep unwrapping while there's a value
-        .map_while(|val: Result<Option<f32>, String>| match val {
-            Ok(v) => v,
+/// Environment variable naming the operation to use when no subcommand is given.
+const OPERATION_ENV: &str = "MATHCLI_OP";
+
+/// Go!
+fn main() {
+    let opts = match resolve_operation(Opts::parse(), std::env::args().collect(), std::env::var(OPERATION_ENV).ok()) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        }
+    };
+    let subcmd = opts.subcmd.as_ref().expect("operation is resolved");
+    let ending = opts.line_ending;
+    if let SubCommand::ListOperations { json } = subcmd {
+        let operations = operations();
+        match json {
+            true => {
+                let objects: Vec<String> = operations.iter()
+                    .map(|(name, identity, description)| format!("{{\"name\": {}, \"identity\": {}, \"description\": {}}}",
+                        json::quote(name), identity.as_deref().map_or("null".to_string(), json::quote), json::quote(description)))
+                    .collect();
+                print!("[{}]{}", objects.join(", "), ending);
+            },
+            false => for (name, identity, description) in &operations {
+                print!("{}\t{}\t{}{}", name, identity.as_deref().unwrap_or("-"), description, ending);
+            }
+        }
+        return
+    }
+    let identity = match subcmd {
+        SubCommand::Mul { .. } | SubCommand::Div => 1.,
+        _ => 0.
+    };
+    let operator = subcmd.operator(opts.float_bits);
+    let to_syslog = match opts.syslog {
+        true => system_log::init(opts.syslog_facility, &opts.syslog_tag, opts.verbose),
+        false => Ok(())
+    };
+    if opts.syslog && to_syslog.is_ok() {
+        log::debug!("Logging to syslog");
+    } else {
+        stderrlog::new()
+            .verbosity(opts.verbose)
+            .init()
+            .unwrap();
+    }
+    if let Err(e) = to_syslog {
+        log::warn!("{}, logging to stderr", e);
+    }
+    if opts.kahan && !subcmd.is_additive() {
+        log::warn!("--kahan only applies to additive operations, like add");
+    }
+    log::info!("Starting...");
+    let input_handler = InputHandler::new(&opts, identity);
+    let formatter = format::OutputFormatter::new(&opts);
+    let start = match &opts.seed_from_file {
+        Some(path) => match read_seed(path) {
+            Ok(seed) => Some(opts.float_bits.round(seed)),
             Err(e) => {
-                // We only get here if --ignore-parse-error is false (which is the default)
                 log::error!("{}", e);
-                None
+                std::process::exit(1)
             }
-        })
+        },
+        None if opts.identity_starting_point => Some(identity),
+        None => None
+    };
+    let profile = profile::Profile::start();
+    let report = || {
+        if opts.profile {
+            profile.report().iter().for_each(|counter| eprintln!("{}", counter));
+        }
+        if opts.report_lines {
+            profile.line_report().iter().for_each(|counter| eprintln!("{}", counter));
+        }
+        exit_if_aborted();
+        finish_commands();
+    };
+    let decimals = Cell::new(0);
+    let span = timestamp::Span::default();
+    let line = Cell::new(0);
+    let current_input = Rc::new(Cell::new(0));
+    let clean = |reader| -> Box<dyn Iterator<Item=(usize, String)> + '_> {
+        let cleaned_input = input_handler.clean_and_enumerate(profile.counted(reader))
+            .inspect(|(i, _)| {
+                line.set(*i);
+                profile.count_line();
+                profile.reach_line(*i);
+            });
+        let cleaned_input: Box<dyn Iterator<Item=(usize, String)>> = match opts.expect_tokens {
+            Some(expected) => Box::new(input_handler.check_tokens(cleaned_input, expected)),
+            None => Box::new(cleaned_input)
+        };
+        let cleaned_input: Box<dyn Iterator<Item=(usize, String)>> = match opts.timestamp_column {
+            Some(column) => Box::new(input_handler.track_span(cleaned_input, column, &span)),
+            None => Box::new(cleaned_input)
+        };
+        let cleaned_input: Box<dyn Iterator<Item=(usize, String)>> = match opts.columns {
+            Some(range) => Box::new(input_handler.split_columns(cleaned_input, range)),
+            None => Box::new(cleaned_input)
+        };
+        match opts.match_input_precision {
+            true => Box::new(input_handler.track_decimals(cleaned_input, &decimals)),
+            false => Box::new(cleaned_input)
+        }
+    };
+    let inputs = match inputs(&opts) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1)
+        }
+    };
+    if let SubCommand::CountMatching { pattern } = subcmd {
+        let re = match Regex::new(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                log::error!("Invalid pattern {}: {}", pattern, e);
+                std::process::exit(1)
+            }
+        };
+        log::info!("Counting...");
+        print!("{}{}", input_handler.count_matching(input_handler.clean_and_enumerate(reader(&inputs)), &re), ending);
+        finish_commands();
+        return
     }
+    let buffered = |values: &mut dyn Iterator<Item=f64>| -> Vec<f64> {
+        log::info!("Buffering...");
+        match buffer(values, opts.max_buffer_count) {
+            Ok(values) => {
+                profile.buffer(values.len());
+                values
+            },
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        }
+    };
+    let errors = Cell::new(0);
+    let (current_line, ignore, counters) = (&line, opts.ignore, &profile);
+    let as_identity = ignored_as_identity(&opts, subcmd);
+    let parse = |lines| -> Box<dyn Iterator<Item=f64> + '_> {
+        let values: Box<dyn Iterator<Item=f64>> = match (opts.collect_errors, opts.error_context) {
+            (true, _) => Box::new(input_handler.parse_collecting_errors(lines, &errors)),
+            (false, Some(context)) => Box::new(input_handler.parse_with_context(lines, context)),
+            (false, None) => Box::new(input_handler.parse_input(lines))
+        };
+        // once the values run out, find out if an error that fails the run is why
+        let values = values.chain(std::iter::from_fn(|| {
+            exit_if_aborted();
+            None
+        }));
+        let values = values.inspect(move |_| {
+            counters.count_value();
+            if current_line.get() < ignore {
+                counters.count_ignored();
+            }
+        });
+        let values: Box<dyn Iterator<Item=f64>> = match as_identity {
+            true => Box::new(values),
+            false => Box::new(values.filter(move |_| current_line.get() >= ignore))
+        };
+        match subcmd {
+            SubCommand::First => Box::new(values.take(1)),
+            _ => values
+        }
+    };
+    // looked up before anything reads the input, which a hit doesn't need
+    let cache = cache_dir(&opts).and_then(|dir| cache_key(&cache_args(&opts, start), &inputs).map(|key| (dir, key)));
+    let cached = cache.as_ref().and_then(|(dir, key)| cache::Entry::load(dir, key));
+    let parsed_lines: Box<dyn Iterator<Item=f64>> = if cached.is_some() {
+        Box::new(std::iter::empty())
+    } else if opts.merge_sorted {
+        let sources = inputs.iter()
+            .map(|input| parse(clean(open(input))))
+            .collect();
+        Box::new(merge::MergeSorted::new(sources))
+    } else if opts.per_file {
+        log::info!("Folding each input...");
+        let sources = inputs.iter().map(|input| parse(clean(open(input))));
+        let results = fold_each(sources, operator, start);
+        match opts.weighted_by_line_count {
+            true => {
+                let means: Vec<(f64, usize)> = results.into_iter()
+                    .filter(|(_, count)| *count > 0)
+                    .map(|(result, count)| (result / count as f64, count))
+                    .collect();
+                Box::new(weighted_mean(&means).map(|mean| opts.float_bits.round(mean)).into_iter())
+            }
+            false => Box::new(results.into_iter().map(|(result, _)| result))
+        }
+    } else if opts.json_array {
+        let mut text = String::new();
+        if let Err(e) = profile.counted(reader(&inputs)).read_to_string(&mut text) {
+            log::error!("Failed to read the JSON array: {}", e);
+            std::process::exit(1)
+        }
+        let elements = match json::elements(&text) {
+            Ok(elements) => elements,
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        };
+        parse(Box::new(elements.into_iter().enumerate().inspect(|(i, _)| {
+            line.set(*i);
+            profile.count_line();
+            profile.reach_line(*i);
+        })))
+    } else {
+        let cleaned_input = match opts.file_subtotals {
+            true => clean(marked_reader(&inputs, &current_input)),
+            false if opts.wait_for_data => {
+                let inputs = inputs.clone();
+                clean(Box::new(wait::Patient::spawn(move || reader(&inputs), Duration::from_millis(opts.wait_timeout_ms))))
+            },
+            false => clean(reader(&inputs))
+        };
+        if opts.count_tokens {
+            log::info!("Counting tokens...");
+            for count in input_handler.token_counts(cleaned_input) {
+                print!("{}{}", count, ending);
+            }
+            report();
+            return
+        }
+        if opts.per_field {
+            log::info!("Folding each field...");
+            let results = input_handler.fold_fields(cleaned_input, operator, start);
+            exit_if_aborted();
+            match results {
+                Ok(results) => {
+                    let results: Vec<String> = results.into_iter().map(|result| formatter.format(result)).collect();
+                    print!("{}{}", formatter.join(&results), ending);
+                },
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1)
+                }
+            }
+            report();
+            return
+        }
+        if opts.transpose {
+            log::info!("Transposing...");
+            let columns = match input_handler.transpose(cleaned_input, opts.transpose_pad, opts.max_buffer_count) {
+                Ok(columns) => columns,
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1)
+                }
+            };
+            exit_if_aborted();
+            profile.buffer(columns.iter().map(Vec::len).sum());
+            for (result, _) in fold_each(columns.into_iter().map(Vec::into_iter), operator, start) {
+                print!("{}{}", formatter.format(result), ending);
+            }
+            report();
+            return
+        }
+        if let Some(key) = opts.group_by {
+            log::info!("Grouping...");
+            let keyed = input_handler.parse_keyed(key, cleaned_input);
+            if opts.grouped_sorted {
+                for group in group::group_sorted(keyed, operator, start) {
+                    match group {
+                        Ok((key, result)) => print!("{}{}", formatter.join(&[key, formatter.format(result)]), ending),
+                        Err(e) => {
+                            log::error!("{}", e);
+                            std::process::exit(1)
+                        }
+                    }
+                }
+                report();
+                return
+            }
+            let groups = group::group_by(keyed, operator, start);
+            exit_if_aborted();
+            let formatter = match opts.match_input_precision {
+                true => formatter.with_precision(decimals.get()),
+                false => formatter
+            };
+            let rows: Vec<Vec<String>> = groups.into_iter()
+                .map(|(key, result)| vec![key, formatter.format(result)])
+                .collect();
+            let lines = match opts.format_table {
+                true => format::table(&["key", "result"], &rows),
+                false => rows.iter().map(|row| formatter.join(row)).collect()
+            };
+            for line in lines {
+                print!("{}{}", line, ending);
+            }
+            report();
+            return
+        }
+        parse(cleaned_input)
+    };
+    let out_of_order = Cell::new(None);
+    let parsed_lines: Box<dyn Iterator<Item=f64>> = match opts.assert_monotonic {
+        Some(order) => Box::new(monotonic::check(parsed_lines, order, &line, opts.ignore, &out_of_order)),
+        None => parsed_lines
+    };
+    let mut parsed_lines: Box<dyn Iterator<Item=f64>> = match opts.sample_rate {
+        Some(rate) => {
+            let seed = opts.sample_seed.unwrap_or_else(|| std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64));
+            log::info!("Sampling with seed {}", seed);
+            Box::new(sample::sample(parsed_lines, rate, sample::Rng::new(seed)))
+        },
+        None => parsed_lines
+    };
+    let parsed_lines: Box<dyn Iterator<Item=f64>> = match opts.zscore_filter {
+        Some(threshold) => {
+            let values = buffered(&mut parsed_lines);
+            let kept = transform::zscore_filter(values, threshold);
+            Box::new(kept.into_iter())
+        },
+        None => parsed_lines
+    };
+    let parsed_lines: Box<dyn Iterator<Item=f64>> = match opts.dedup_consecutive {
+        true => Box::new(transform::dedup_consecutive(parsed_lines, opts.epsilon)),
+        false => parsed_lines
+    };
+    let zeros = Cell::new(0);
+    let mut parsed_lines: Box<dyn Iterator<Item=f64>> = match subcmd {
+        SubCommand::Mul { skip_zeros: true } => Box::new(skip_zeros(parsed_lines, &zeros)),
+        _ => parsed_lines
+    };
+    // the starting point already went into every input's result
+    let (subcmd, start) = match opts.per_file {
+        true => (opts.merge_op.as_ref().unwrap_or(subcmd), None),
+        false => (subcmd, start)
+    };
+    let operator = subcmd.operator(opts.float_bits);
 
-    /// Handles a value and its index according to the flags specified by the user.
-    fn handle(self, i: usize, val: &str) -> Result<Option<f32>, String> {
-        if i < self.ignore {
-            log::debug!("Ignored value {}", val);
-            return Ok(Some(self.identity))
+    let mut status = None;
+    if let SubCommand::TrimmedMean { percent } = subcmd {
+        let values = buffered(&mut parsed_lines);
+        match transform::trimmed_mean(values, *percent, opts.kahan) {
+            Ok(mean) => print!("{}{}", formatter.format(opts.float_bits.round(mean)), ending),
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
         }
-        if val.is_empty() {
-            log::debug!("Found empty at line number {}, exiting.", i + 1);
-            return Ok(None)
+    } else if let SubCommand::CountDistinct { epsilon } = subcmd {
+        let values = buffered(&mut parsed_lines);
+        print!("{}{}", transform::count_distinct(values, *epsilon), ending);
+    } else if let SubCommand::Checksum = subcmd {
+        log::info!("Hashing...");
+        print!("{:016x}{}", transform::checksum(parsed_lines), ending);
+    } else if opts.percent_of_total {
+        let values = buffered(&mut parsed_lines);
+        if let Some(e) = out_of_order.take() {
+            log::error!("{}", e);
+            std::process::exit(1)
         }
-        return match val.parse::<f32>() {
-            Ok(v) => Ok(Some(v)),
+        match transform::percent_of_total(&values) {
+            Ok(percents) => for (value, percent) in values.iter().zip(percents) {
+                print!("{}{}", formatter.format_transformed(*value, percent, "%"), ending);
+            },
             Err(e) => {
-                match self.silent {
-                    true => {
-                        log::warn!("Ignoring parse error {} for {} at line {}", e, val, i + 1);
-                        Ok(Some(self.identity))
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        }
+    } else if opts.softmax {
+        let values = buffered(&mut parsed_lines);
+        if let Some(e) = out_of_order.take() {
+            log::error!("{}", e);
+            std::process::exit(1)
+        }
+        for (value, probability) in values.iter().zip(transform::softmax(&values)) {
+            print!("{}{}", formatter.format_transformed(*value, probability, ""), ending);
+        }
+    } else if opts.pct_change {
+        log::info!("Writing percent changes");
+        for change in transform::pct_change(parsed_lines) {
+            match change {
+                Ok((value, change)) => print!("{}{}", formatter.format_transformed(value, change, "%"), ending),
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1)
+                }
+            }
+        }
+    } else if let Some(size) = opts.running_window_stats {
+        log::info!("Writing window stats");
+        for stat in window::rolling(parsed_lines, size, opts.window_stat) {
+            print!("{}{}", formatter.format(stat), ending);
+        }
+    } else if opts.emit_merged {
+        log::info!("Writing merged stream");
+        for value in parsed_lines {
+            print!("{}{}", opts.float_bits.display(value), ending);
+        }
+    } else {
+        log::info!("Folding...");
+        let count = Cell::new(0);
+        // only values from lines --ignore kept count towards --min-values
+        let parsed_lines = parsed_lines.inspect(|_| if line.get() >= opts.ignore {
+            count.set(count.get() + 1);
+        });
+        let summary = Cell::new((0, None));
+        let parsed_lines = parsed_lines.inspect(|&val| if line.get() >= opts.ignore {
+            summary.set(summarize(summary.get(), val));
+        });
+        let result = match cached {
+            Some(entry) => {
+                log::info!("Using the cached result");
+                count.set(entry.count);
+                summary.set(entry.summary);
+                decimals.set(entry.decimals);
+                Some(entry.result)
+            },
+            None => {
+                let result = match &opts.checkpoint_file {
+                    Some(path) => {
+                        let resume = match opts.resume {
+                            true => checkpoint::Checkpoint::load(path),
+                            false => None
+                        };
+                        checkpoint::fold(parsed_lines, operator, start, path, opts.checkpoint_every, resume)
                     },
-                    false => {
-                        log::debug!("{}", e);
-                        Err(format!("Failed to parse {} at line {}", val, i + 1))
+                    None if opts.align_output => {
+                        let mut steps = Vec::new();
+                        let result = fold_showing_steps(parsed_lines, operator, subcmd.symbol(), opts.float_bits, start, |step| steps.push(step));
+                        format::align(&steps).iter().for_each(|step| eprintln!("{}", step));
+                        result
+                    },
+                    None if opts.show_steps => fold_showing_steps(parsed_lines, operator, subcmd.symbol(), opts.float_bits, start, |step| eprintln!("{}", step)),
+                    None if opts.file_subtotals => fold_with_subtotals(parsed_lines, operator, start, &current_input, |i, subtotal| {
+                        eprintln!("{} {}", inputs[i], formatter.format(subtotal))
+                    }),
+                    None if opts.signal_handling => {
+                        let progress = Arc::new(interrupt::Progress::default());
+                        let interrupted = Arc::clone(&progress);
+                        let handled = ctrlc::set_handler(move || {
+                            match interrupted.get() {
+                                Some(result) => print!("{}{}", formatter.format(result), ending),
+                                None => log::warn!("Interrupted before any values were read")
+                            }
+                            std::process::exit(130)
+                        });
+                        if let Err(e) = handled {
+                            log::warn!("Failed to handle Ctrl-C: {}", e);
+                        }
+                        interrupt::fold(parsed_lines, operator, start, &progress)
+                    },
+                    None if opts.kahan && subcmd.is_additive() => transform::compensated_sum(parsed_lines, start).map(|total| opts.float_bits.round(total)),
+                    None => fold(parsed_lines, operator, start)
+                };
+                if let Some(e) = out_of_order.take() {
+                    log::error!("{}", e);
+                    std::process::exit(1)
+                }
+                let result = match zeros.get() {
+                    0 => result,
+                    skipped => {
+                        log::info!("Skipped {} zeros", skipped);
+                        result.or(Some(identity))
+                    }
+                };
+                let result = match subcmd {
+                    SubCommand::Rate { duration } => match result.map(|total| rate(total, *duration, span.seconds())) {
+                        Some(Ok(rate)) => Some(opts.float_bits.round(rate)),
+                        Some(Err(e)) => {
+                            log::error!("{}", e);
+                            std::process::exit(1)
+                        },
+                        None => None
+                    },
+                    _ => result
+                };
+                result
+            }
+        };
+        if let (Some((dir, key)), None, Some(result)) = (&cache, cached, result) {
+            if errors.get() == 0 {
+                let entry = cache::Entry { result, count: count.get(), summary: summary.get(), decimals: decimals.get() };
+                if let Err(e) = entry.save(dir, key) {
+                    log::warn!("Failed to write the cache entry {}: {}", key, e);
+                }
+            }
+        }
+        if let Err(e) = enough_values(count.get(), opts.min_values) {
+            log::error!("{}", e);
+            std::process::exit(1)
+        }
+        let result = match result {
+            Some(result) => result,
+            None => {
+                log::error!("No values were read");
+                std::process::exit(1)
+            }
+        };
+        let result = match apply_then(result, &opts.then, &opts.then_value, opts.float_bits) {
+            Ok(result) => result,
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        };
+        let formatter = match opts.match_input_precision {
+            true => formatter.with_precision(decimals.get()),
+            false => formatter
+        };
+        let formatted = match (opts.output_hex, opts.output_ratio) {
+            (true, _) => format::hex(result),
+            (false, Some(reference)) => formatter.ratio(result, reference, opts.ratio_decimal),
+            (false, None) => Ok(formatter.format(result))
+        };
+        let formatted = match formatted {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        };
+        let formatted = match &opts.template {
+            Some(template) => {
+                let (counted, extent) = summary.get();
+                let (min, max) = extent.map_or(("-".to_string(), "-".to_string()), |(min, max)| (formatter.format(min), formatter.format(max)));
+                template.fill(&[("result", formatted), ("count", counted.to_string()), ("operation", subcmd.name().to_string()), ("min", min), ("max", max)])
+            },
+            None => formatted
+        };
+        match opts.quiet_result {
+            true => log::info!("Result {}", formatted),
+            false => {
+                log::info!("Writing result");
+                if let Err(e) = write_result(&formatted, ending, &mut io::stdout(), opts.echo_stderr, &mut io::stderr()) {
+                    log::error!("Failed to write the result: {}", e);
+                    std::process::exit(1)
+                }
+                if opts.syslog {
+                    let sent = system_log::connect(opts.syslog_facility, &opts.syslog_tag)
+                        .and_then(|mut logger| logger.info(system_log::result_message(&formatted)).map_err(|e| e.to_string()));
+                    if let Err(e) = sent {
+                        log::warn!("Failed to send the result to syslog: {}", e);
                     }
                 }
             }
         }
+        if let Some(path) = &opts.history_file {
+            let appended = history::append(path, &history::timestamp(std::time::SystemTime::now()), subcmd.name(), &opts.float_bits.display(result));
+            if let Err(e) = appended {
+                log::error!("Failed to append the result to {}: {}", path.display(), e);
+                std::process::exit(1)
+            }
+        }
+        for format in &opts.also {
+            match formatter.also(result, *format) {
+                Ok(formatted) => print!("{}{}", formatted, ending),
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1)
+                }
+            }
+        }
+        if opts.compare_gt.is_some() || opts.compare_lt.is_some() {
+            let verdict = compare(result, opts.compare_gt, opts.compare_lt, opts.epsilon, opts.float_bits);
+            let status = match verdict {
+                Ok(()) => "PASS",
+                Err(_) => "FAIL"
+            };
+            match &opts.compare_message {
+                Some(message) => print!("{} {}{}", status, message, ending),
+                None => print!("{}{}", status, ending)
+            }
+            if let Err(e) = verdict {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        }
+        if opts.assert_nonzero {
+            if let Err(e) = nonzero(result, opts.epsilon, opts.float_bits) {
+                log::error!("{}", e);
+                std::process::exit(1)
+            }
+        }
+        if opts.exit_code_from_result {
+            match exit_code(result) {
+                Ok(code) => status = Some(code),
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1)
+                }
+            }
+        }
+    }
+
+    report();
+    if errors.get() > 0 {
+        log::error!("{} lines failed to parse", errors.get());
+        std::process::exit(1)
+    }
+    if let Some(code) = status {
+        std::process::exit(code)
     }
 }
 
+thread_local! {
+    /// Set when the input turns up an error that fails the whole run rather than just ending the
+    /// input, like a NaN under --strict-finite. The error is logged where it's found. The input is
+    /// parsed on the main thread, and each test gets its own.
+    static ABORTED: Cell<bool> = const { Cell::new(false) };
+}
 
-#[cfg(test)]
-mod tests {
+/// Fail the whole run once the input stops, for `exit_if_aborted`.
+fn abort() {
+    ABORTED.with(|aborted| aborted.set(true));
+}
 
-    use super::InputHandler;
+/// Whether the input failed the run with `abort`.
+fn aborted() -> bool {
+    ABORTED.with(Cell::get)
+}
 
-    fn handler(silent: bool) -> InputHandler {
-        InputHandler {
-            ignore: 2,
-            silent,
-            identity: 1.5
-        }
+/// End the run if the input failed it with `abort`, before any result is printed.
+fn exit_if_aborted() {
+    if aborted() {
+        std::process::exit(1)
     }
+}
 
-    #[test]
-    fn test_trim_lines() {
-        let handler = handler(false);
-        for (k, v) in handler.clean_and_enumerate(b"\t\t1\n2\t\t\n   3   \n" as &[u8]) {
-            match k {
-                0 => assert_eq!("1", v),
-                1 => assert_eq!("2", v),
-                2 => assert_eq!("3", v),
-                _ => panic!()
-            }
-        }
+/// Wait for the --exec commands, which the run may have stopped reading early, and fail with the
+/// exit code of the first that failed.
+fn finish_commands() {
+    if let Err((message, code)) = exec::wait_all() {
+        log::error!("{}", message);
+        std::process::exit(code)
     }
+}
 
-    #[test]
-    fn test_successful_handle() {
-        let handler = handler(false);
-        assert_eq!(Ok(Some(3.0)), handler.handle(2, "3.0"));
-    }
- 
-    #[test]
-    fn test_ignore_returns_identity() {
-        let handler = handler(false);
-        assert_eq!(Ok(Some(1.5)), handler.handle(0, "2.0"));
-        assert_eq!(Ok(Some(1.5)), handler.handle(1, "2.0"));
-        assert_eq!(Ok(Some(3.0)), handler.handle(2, "3.0"));
+/// The name, identity if it has one, and description of every visible operation, from the help
+/// of the subcommands.
+fn operations() -> Vec<(String, Option<String>, String)> {
+    Opts::into_app().get_subcommands().iter()
+        .filter(|app| !app.is_set(clap::AppSettings::Hidden))
+        .map(|app| {
+            let about = app.get_about().unwrap_or_default();
+            let (description, identity) = match about.split_once("Identity:") {
+                Some((description, identity)) => (description, Some(identity.trim().to_string())),
+                None => (about, None)
+            };
+            (app.get_name().to_string(), identity, description.trim().to_string())
+        })
+        .collect()
+}
+
+/// Make sure `opts` has an operation. When no subcommand was given, `args` are parsed again with
+/// the operation from the environment appended, so it behaves exactly like the subcommand.
+/// Arguments of the operation that don't make sense together are an error too.
+fn resolve_operation(opts: Opts, args: Vec<String>, env_op: Option<String>) -> Result<Opts, String> {
+    let opts = match opts.subcmd {
+        Some(_) => opts,
+        None => {
+            let op = env_op.ok_or(format!("No operation given, pass a subcommand or set {}", OPERATION_ENV))?;
+            Opts::try_parse_from(args.into_iter().chain(op.split_whitespace().map(String::from)))
+                .map_err(|e| format!("Invalid {} \"{}\": {}", OPERATION_ENV, op, e))?
+        }
+    };
+    if let Some(SubCommand::CountBetween { low, high, .. }) = opts.subcmd {
+        if low > high {
+            return Err(format!("count-between's low bound {} is above its high bound {}", low, high))
+        }
     }
+    Ok(opts)
+}
 
-    #[test]
-    fn test_exit_on_empty_line() {
-        let handler = handler(false);
-        assert_eq!(Ok(None), handler.handle(2, ""));
+/// The --cache-dir to use, unless the result could come out differently from the same inputs.
+fn cache_dir(opts: &Opts) -> Option<&PathBuf> {
+    match (&opts.cache_dir, opts.sample_rate, opts.sample_seed) {
+        (Some(_), Some(_), None) => {
+            log::info!("Not caching the result, --sample-rate without --sample-seed samples differently every run");
+            None
+        },
+        (Some(_), _, _) if opts.resume => {
+            log::info!("Not caching the result, --resume carries on from the checkpoint");
+            None
+        },
+        (dir, _, _) => dir.as_ref()
     }
+}
 
-    #[test]
-    fn test_exit_on_parse_error() {
-        let handler = handler(false);
-        let input_string = "notf32";
-        let msg = format!("Failed to parse {} at line {}", &input_string, 3);
-        assert_eq!(Err(msg), handler.handle(2, input_string));
+/// What the cached result of a run depends on besides the contents of its inputs: the operation,
+/// where it starts, and the options that change which values are read and how. Options that only
+/// change how the result is written, checked or logged are left out, as are the input paths.
+fn cache_args(opts: &Opts, start: Option<f64>) -> Vec<String> {
+    let subcmd = opts.subcmd.as_ref().expect("operation is resolved");
+    vec![
+        format!("{:?}", subcmd),
+        format!("start={:?}", start),
+        format!("ignore={} silent={} empty-as={:?} on-error-value={:?}", opts.ignore, opts.silent, opts.empty_as, opts.on_error_value),
+        format!("merge-sorted={} per-file={} merge-op={:?} weighted-by-line-count={} json-array={}",
+            opts.merge_sorted, opts.per_file, opts.merge_op, opts.weighted_by_line_count, opts.json_array),
+        format!("column={:?} timestamp-column={:?} csv={} skip-empty-tokens={} columns={:?} multiplier-column={:?}",
+            opts.column, opts.timestamp_column, opts.csv, opts.skip_empty_tokens, opts.columns, opts.multiplier_column),
+        format!("negate={} reciprocal={} log={} exp={} round-to-multiple={:?}",
+            opts.negate, opts.reciprocal, opts.log, opts.exp, opts.round_to_multiple),
+        format!("input-encoding={} input-as-duration={} no-scientific-input={} ignore-case={} bool-as-number={} strip-ansi={} base64={} space-grouped={}",
+            opts.input_encoding.name(), opts.input_as_duration, opts.no_scientific_input, opts.ignore_case,
+            opts.bool_as_number, opts.strip_ansi, opts.base64, opts.space_grouped),
+        format!("input-locale={:?}", opts.input_locale),
+        format!("wait-for-data={} wait-timeout-ms={} ignore-blank-only-lines-anywhere={}",
+            opts.wait_for_data, opts.wait_timeout_ms, opts.ignore_blank_only_lines_anywhere),
+        format!("sample-rate={:?} sample-seed={:?} zscore-filter={:?} dedup-consecutive={} epsilon={}",
+            opts.sample_rate, opts.sample_seed, opts.zscore_filter, opts.dedup_consecutive, opts.epsilon),
+        format!("kahan={} float-bits={:?} match-input-precision={}", opts.kahan, opts.float_bits, opts.match_input_precision),
+    ]
+}
+
+/// The --cache-dir key of this run, if every input is a file that can be read.
+fn cache_key(args: &[String], inputs: &[Input]) -> Option<String> {
+    let files: Option<Vec<&Path>> = inputs.iter()
+        .map(|input| match input {
+            Input::File(path) => Some(path.as_path()),
+            _ => None
+        })
+        .collect();
+    let files = match files {
+        Some(files) => files,
+        None => {
+            log::info!("Not caching the result, not every input is a file");
+            return None
+        }
+    };
+    match cache::key(args, &files) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            log::warn!("Not caching the result: {}", e);
+            None
+        }
     }
+}
 
-    #[test]
+/// Adds `val` to the count and the smallest and largest value so far, for --template.
+fn summarize((count, extent): (usize, Option<(f64, f64)>), val: f64) -> (usize, Option<(f64, f64)>) {
+    let extent = match extent {
+        Some((min, max)) => (min.min(val), max.max(val)),
+        None => (val, val)
+    };
+    (count + 1, Some(extent))
+}
+
+/// Fold `values` from `start`, or reduce them when there's no starting point.
+fn fold(values: impl Iterator<Item=f64>, operator: fn(f64, f64) -> f64, start: Option<f64>) -> Option<f64> {
+    match start {
+        Some(start) => Some(values.fold(start, operator)),
+        None => values.reduce(operator)
+    }
+}
+
+/// `fold` each of `sources` on its own, with the number of values that went in, leaving out the
+/// ones without a result.
+fn fold_each<I: Iterator<Item=f64>>(sources: impl Iterator<Item=I>, operator: fn(f64, f64) -> f64, start: Option<f64>) -> Vec<(f64, usize)> {
+    sources.filter_map(|values| {
+        let mut count = 0;
+        let result = fold(values.inspect(|_| count += 1), operator, start);
+        result.map(|result| (result, count))
+    }).collect()
+}
+
+/// The mean of `(value, weight)` pairs weighted by their weights, `None` without any weight.
+fn weighted_mean(weighted: &[(f64, usize)]) -> Option<f64> {
+    let total: usize = weighted.iter().map(|(_, weight)| weight).sum();
+    match total {
+        0 => None,
+        _ => Some(weighted.iter().map(|(value, weight)| value * *weight as f64).sum::<f64>() / total as f64)
+    }
+}
+
+/// `fold`, passing `report` the index of every input with values and the result up to its end.
+/// `current` holds the index of the input the latest value was read from.
+fn fold_with_subtotals(
+    values: impl Iterator<Item=f64>,
+    operator: fn(f64, f64) -> f64,
+    start: Option<f64>,
+    current: &Cell<usize>,
+    mut report: impl FnMut(usize, f64),
+) -> Option<f64> {
+    let mut last_input = None;
+    let result = values.fold(start, |acc, val| {
+        let input = current.get();
+        if let (Some(last), Some(acc)) = (last_input, acc) {
+            if last != input {
+                report(last, acc);
+            }
+        }
+        last_input = Some(input);
+        Some(acc.map_or(val, |acc| operator(acc, val)))
+    });
+    if let (Some(last), Some(result)) = (last_input, result) {
+        report(last, result);
+    }
+    result
+}
+
+/// Fold like `Iterator::fold`/`reduce`, passing a description of every step to `show`.
+fn fold_showing_steps(
+    values: impl Iterator<Item=f64>,
+    operator: fn(f64, f64) -> f64,
+    symbol: char,
+    bits: FloatBits,
+    start: Option<f64>,
+    mut show: impl FnMut(String),
+) -> Option<f64> {
+    values.fold(start, |acc, x| match acc {
+        Some(acc) => {
+            let result = operator(acc, x);
+            show(format!("{} {} {} = {}", bits.display(acc), symbol, bits.display(x), bits.display(result)));
+            Some(result)
+        },
+        None => Some(x)
+    })
+}
+
+/// Leave the zeros out of `values`, counting them in `skipped`.
+fn skip_zeros<'a>(values: impl Iterator<Item=f64> + 'a, skipped: &'a Cell<usize>) -> impl Iterator<Item=f64> + 'a {
+    values.filter(move |v| match *v == 0. {
+        true => {
+            skipped.set(skipped.get() + 1);
+            false
+        },
+        false => true
+    })
+}
+
+/// Combine `result` with each of `values` in turn, using the operation at the same position.
+fn apply_then(result: f64, ops: &[SubCommand], values: &[f64], bits: FloatBits) -> Result<f64, String> {
+    if ops.len() != values.len() {
+        return Err(format!("Got {} --then operations but {} --then-values", ops.len(), values.len()))
+    }
+    Ok(ops.iter().zip(values).fold(result, |acc, (op, val)| op.operator(bits)(acc, bits.round(*val))))
+}
+
+/// Checks `result` against --compare-gt and --compare-lt, describing the first that doesn't hold.
+/// A result within `epsilon` of a bound passes it.
+fn compare(result: f64, gt: Option<f64>, lt: Option<f64>, epsilon: f64, bits: FloatBits) -> Result<(), String> {
+    let close = |bound: f64| epsilon > 0. && (result - bound).abs() <= epsilon;
+    match (gt, lt) {
+        (Some(gt), _) if result.partial_cmp(&gt) != Some(Ordering::Greater) && !close(gt) =>
+            Err(format!("{} is not greater than {}", bits.display(result), gt)),
+        (_, Some(lt)) if result.partial_cmp(&lt) != Some(Ordering::Less) && !close(lt) =>
+            Err(format!("{} is not less than {}", bits.display(result), lt)),
+        _ => Ok(())
+    }
+}
+
+/// The exit status for --exit-code-from-result: the whole part of `result` modulo 256.
+fn exit_code(result: f64) -> Result<i32, String> {
+    match result.is_finite() {
+        true => Ok(result.trunc().rem_euclid(256.) as i32),
+        false => Err(format!("Can't exit with a result of {}", result))
+    }
+}
+
+/// Checks the result isn't 0, or within `epsilon` of it, for --assert-nonzero.
+fn nonzero(result: f64, epsilon: f64, bits: FloatBits) -> Result<(), String> {
+    match result.abs() <= epsilon {
+        true => Err(format!("The result {} is zero", bits.display(result))),
+        false => Ok(())
+    }
+}
+
+/// The rate of `total` over `duration` seconds, or over the `span` of the timestamps.
+fn rate(total: f64, duration: Option<f64>, span: Option<f64>) -> Result<f64, String> {
+    match duration.or(span) {
+        Some(seconds) if seconds > 0. => Ok(total / seconds),
+        Some(_) => Err("Can't take a rate over no time, the timestamps are all the same".to_string()),
+        None => Err("rate needs a --duration or a --timestamp-column".to_string())
+    }
+}
+
+/// Write the result line to `out` ending with `ending`, and to `err` too with `echo`.
+fn write_result(formatted: &str, ending: format::LineEnding, out: &mut impl Write, echo: bool, err: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}{}", formatted, ending)?;
+    if echo {
+        write!(err, "{}{}", formatted, ending)?;
+    }
+    Ok(())
+}
+
+/// Collect the values a mode needs all at once, failing as soon as there are more than `max`.
+fn buffer(values: &mut dyn Iterator<Item=f64>, max: Option<usize>) -> Result<Vec<f64>, String> {
+    let mut buffered = Vec::new();
+    for val in values {
+        if let Some(max) = max.filter(|max| buffered.len() == *max) {
+            return Err(too_many_buffered(max))
+        }
+        buffered.push(val);
+    }
+    Ok(buffered)
+}
+
+fn too_many_buffered(max: usize) -> String {
+    format!("More than {} values to hold in memory, --max-buffer-count may need raising", max)
+}
+
+/// Whether ignored lines go into the operation as its identity, as they do when it's folded.
+/// Every other mode, and anything that looks at the values on their way to the fold, needs only
+/// the values actually read, so ignored lines are left out as soon as they're parsed.
+fn ignored_as_identity(opts: &Opts, subcmd: &SubCommand) -> bool {
+    let folded = !matches!(subcmd, SubCommand::TrimmedMean { .. } | SubCommand::CountDistinct { .. } | SubCommand::Checksum | SubCommand::First | SubCommand::Last);
+    let listed = opts.percent_of_total || opts.softmax || opts.pct_change || opts.running_window_stats.is_some() || opts.emit_merged;
+    let filtered = opts.merge_sorted || opts.zscore_filter.is_some() || opts.dedup_consecutive || (opts.per_file && opts.weighted_by_line_count);
+    folded && !listed && !filtered
+}
+
+/// Checks that `count` values are enough for --min-values.
+fn enough_values(count: usize, min: usize) -> Result<(), String> {
+    match count < min {
+        true => Err(format!("Only {} values were read, at least {} are needed", count, min)),
+        false => Ok(())
+    }
+}
+
+/// Read the single number saved in `path`.
+fn read_seed(path: &Path) -> Result<f64, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read seed from {}: {}", path.display(), e))?;
+    contents.trim().parse::<f64>()
+        .map_err(|_| format!("Failed to parse seed {} from {}", contents.trim(), path.display()))
+}
+
+/// The range count-between counts values in.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Bounds {
+    low: f64,
+    high: f64,
+    exclusive_low: bool,
+    exclusive_high: bool,
+}
+
+impl Bounds {
+    fn contains(self, val: f64) -> bool {
+        let above = match self.exclusive_low {
+            true => val > self.low,
+            false => val >= self.low
+        };
+        let below = match self.exclusive_high {
+            true => val < self.high,
+            false => val <= self.high
+        };
+        above && below
+    }
+}
+
+/// Somewhere to read input from.
+#[derive(Debug, PartialEq, Clone)]
+enum Input {
+    Stdin,
+    File(PathBuf),
+    Command(String),
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Stdin => write!(f, "stdin"),
+            Input::File(path) => write!(f, "{}", path.display()),
+            Input::Command(command) => write!(f, "{}", command)
+        }
+    }
+}
+
+/// The inputs to read, in order. Stdin alone when there are no files or commands, and alongside
+/// them only when asked for with --stdin-first or --files-first.
+fn inputs(opts: &Opts) -> Result<Vec<Input>, String> {
+    let mut files = opts.file.clone();
+    if let Some(pattern) = &opts.input_glob {
+        files.extend(expand_glob(pattern)?);
+    }
+    let mut inputs: Vec<Input> = files.into_iter().map(Input::File).collect();
+    inputs.extend(opts.exec.iter().cloned().map(Input::Command));
+    if inputs.is_empty() || opts.stdin_first {
+        inputs.insert(0, Input::Stdin);
+    } else if opts.files_first {
+        inputs.push(Input::Stdin);
+    }
+    Ok(inputs)
+}
+
+/// The files matching `pattern`, sorted so the order doesn't depend on the file system.
+fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let paths = glob::glob(pattern).map_err(|e| format!("Invalid glob {}: {}", pattern, e))?;
+    let mut files = paths.collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(|e| format!("Failed to read {}: {}", e.path().display(), e.error()))?;
+    if files.is_empty() {
+        return Err(format!("No files match {}", pattern))
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read the inputs one after another.
+fn reader(inputs: &[Input]) -> Box<dyn BufRead> {
+    inputs.iter()
+        .map(open)
+        .fold(Box::new(io::empty()), |acc, r| Box::new(acc.chain(r)))
+}
+
+/// Like `reader`, noting in `current` the index of the input being read.
+fn marked_reader(inputs: &[Input], current: &Rc<Cell<usize>>) -> Box<dyn BufRead> {
+    inputs.iter()
+        .enumerate()
+        .map(|(index, input)| Marked { inner: open(input), index, current: Rc::clone(current) })
+        .fold(Box::new(io::empty()), |acc, r| Box::new(acc.chain(r)))
+}
+
+/// Reads an input, setting `current` to its `index` whenever it hands out data.
+struct Marked {
+    inner: Box<dyn BufRead>,
+    index: usize,
+    current: Rc<Cell<usize>>,
+}
+
+impl Read for Marked {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.current.set(self.index);
+        }
+        Ok(n)
+    }
+}
+
+impl BufRead for Marked {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.inner.fill_buf()?;
+        if !buf.is_empty() {
+            self.current.set(self.index);
+        }
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// Open an input for reading, exiting if it can't be.
+fn open(input: &Input) -> Box<dyn BufRead> {
+    match input {
+        Input::Stdin => Box::new(io::stdin().lock()),
+        Input::File(path) => match File::open(path) {
+            Ok(f) => Box::new(io::BufReader::new(f)),
+            Err(e) => {
+                log::error!("Failed to open {}: {}", path.display(), e);
+                std::process::exit(1)
+            }
+        },
+        Input::Command(command) => match exec::CommandOutput::spawn(command) {
+            Ok(output) => Box::new(output),
+            Err(e) => {
+                log::error!("Failed to run {}: {}", command, e);
+                std::process::exit(1)
+            }
+        }
+    }
+}
+
+/// Responsible for cleaning user input
+#[derive(Copy, Clone)]
+struct InputHandler {
+    identity: f64,
+    ignore: usize,
+    silent: bool,
+    negate: bool,
+    reciprocal: bool,
+    log: bool,
+    exp: bool,
+    round_to_multiple: Option<f64>,
+    column: Option<usize>,
+    multiplier_column: Option<usize>,
+    csv: bool,
+    skip_empty_tokens: bool,
+    encoding: &'static Encoding,
+    duration: bool,
+    ignore_case: bool,
+    no_scientific: bool,
+    log_precision: Option<usize>,
+    empty_as: Option<f64>,
+    count_nonzero: Option<f64>,
+    count_between: Option<Bounds>,
+    square: bool,
+    strict_finite: bool,
+    bits: FloatBits,
+    line_offset: usize,
+    bool_as_number: bool,
+    strip_ansi: bool,
+    base64: bool,
+    on_error_value: Option<f64>,
+    strict: bool,
+    space_grouped: bool,
+    locale: Option<format::Locale>,
+    skip_blank: bool,
+}
+
+impl InputHandler {
+    /// Use the given identity (0 or 1) and options to configure the handler.
+    pub fn new(opts: &Opts, identity: f64) -> Self {
+        InputHandler {
+            ignore: opts.ignore,
+            silent: opts.silent,
+            negate: opts.negate,
+            reciprocal: opts.reciprocal,
+            log: opts.log,
+            exp: opts.exp,
+            round_to_multiple: opts.round_to_multiple,
+            column: opts.column,
+            multiplier_column: opts.multiplier_column,
+            csv: opts.csv,
+            skip_empty_tokens: opts.skip_empty_tokens,
+            encoding: opts.input_encoding,
+            duration: opts.input_as_duration,
+            ignore_case: opts.ignore_case,
+            no_scientific: opts.no_scientific_input,
+            log_precision: opts.log_precision,
+            empty_as: opts.empty_as,
+            count_nonzero: match opts.subcmd {
+                Some(SubCommand::CountNonzero { epsilon }) => Some(epsilon),
+                _ => None
+            },
+            count_between: match opts.subcmd {
+                Some(SubCommand::CountBetween { low, high, exclusive_low, exclusive_high }) => Some(Bounds { low, high, exclusive_low, exclusive_high }),
+                _ => None
+            },
+            square: matches!(opts.subcmd, Some(SubCommand::SumOfSquares)),
+            strict_finite: opts.strict_finite,
+            bits: opts.float_bits,
+            line_offset: opts.line_offset,
+            bool_as_number: opts.bool_as_number,
+            strip_ansi: opts.strip_ansi,
+            base64: opts.base64,
+            on_error_value: opts.on_error_value,
+            strict: opts.strict,
+            space_grouped: opts.space_grouped,
+            locale: opts.input_locale,
+            skip_blank: opts.ignore_blank_only_lines_anywhere,
+            identity
+        }
+    }
+
+    /// Splits the input into trimmed lines paired with their index. A line of only whitespace
+    /// comes out empty, so it stops the input like an empty line unless `skip_blank` leaves it out.
+    fn clean_and_enumerate<R: BufRead>(self, reader: R) -> impl Iterator<Item=(usize, String)> {
+        reader.split(b'\n')
+        // If we fail to read a line due to some io issue, stop, not useful to continue
+        .map_while(|x| match x {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                log::error!("Failed to read input: {}", e);
+                None
+            }
+        })
+        // gives us (index, value). useful for ignoring lines, logging, etc
+        .enumerate()
+        .map_while(move |(i, bytes)| {
+            let (line, had_errors) = self.encoding.decode_without_bom_handling(&bytes);
+            if had_errors {
+                match self.silent {
+                    true => log::warn!("Invalid {} at line {}, decoded as {}", self.encoding.name(), self.line_number(i), line),
+                    false => {
+                        log::error!("Invalid {} at line {}", self.encoding.name(), self.line_number(i));
+                        return None
+                    }
+                }
+            }
+            let line = match self.strip_ansi {
+                true => strip_ansi(&line).into(),
+                false => line
+            };
+            let line = match self.space_grouped {
+                true => join_digit_groups(&line).into(),
+                false => line
+            };
+            // trimming also drops the \r of \r\n line endings, which doesn't make a line blank
+            let trimmed = line.trim();
+            let blank = trimmed.is_empty() && !line.trim_end_matches('\r').is_empty();
+            if self.base64 && i >= self.ignore && !trimmed.is_empty() {
+                match decode_base64(trimmed) {
+                    Ok(decoded) => return Some((i, decoded.trim().to_string(), false)),
+                    Err(e) => match self.silent {
+                        true => log::warn!("Invalid base64 at line {}, reading it as it is: {}", self.line_number(i), e),
+                        false => {
+                            log::error!("Invalid base64 at line {}: {}", self.line_number(i), e);
+                            return None
+                        }
+                    }
+                }
+            }
+            Some((i, trimmed.to_string(), blank))
+        })
+        .filter(move |(i, _, blank)| match self.skip_blank && *blank {
+            true => {
+                log::debug!("Skipped blank line {}", self.line_number(*i));
+                false
+            },
+            false => true
+        })
+        .map(|(i, line, _)| (i, line))
+    }
+
+    /// Reads each value into a float and continues until Err is returned
+    fn parse_input(self, it: impl Iterator<Item=(usize, String)>) -> impl Iterator<Item=f64> {
+        // ignore lines, check for empties, parse to f64, etc
+        it.map(move |(i, val)| self.handle(i, &val))
+        // keep unwrapping while there's a value
+        .map_while(unwrap_handled)
+    }
+
+    /// Like `parse_input`, but lines that fail to parse are logged, counted in `errors` and
+    /// left out rather than stopping the stream, unless they fail the whole run
+    fn parse_collecting_errors<'a>(self, it: impl Iterator<Item=(usize, String)> + 'a, errors: &'a Cell<usize>) -> impl Iterator<Item=f64> + 'a {
+        it.map(move |(i, val)| self.handle(i, &val))
+        .filter_map(move |val| match val {
+            Ok(v) => Some(v),
+            Err(e) => {
+                log::error!("{}", e);
+                errors.set(errors.get() + 1);
+                // an error that fails the whole run still stops it straight away
+                match aborted() {
+                    true => Some(None),
+                    false => None
+                }
+            }
+        })
+        // still stop at the first empty line
+        .map_while(|val| val)
+    }
+
+    /// Like `parse_input`, but when a line fails to parse the `context` lines around it are logged too
+    fn parse_with_context(self, mut it: impl Iterator<Item=(usize, String)>, context: usize) -> impl Iterator<Item=f64> {
+        let mut recent: VecDeque<(usize, String)> = VecDeque::with_capacity(context + 1);
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None
+            }
+            let (i, line) = it.next()?;
+            let handled = self.handle(i, &line);
+            recent.push_back((i, line));
+            if recent.len() > context + 1 {
+                recent.pop_front();
+            }
+            match handled {
+                Ok(Some(v)) => return Some(v),
+                Ok(None) => (),
+                Err(e) => {
+                    log::error!("{}", e);
+                    let after: Vec<(usize, String)> = it.by_ref().take(context).collect();
+                    for line in context_lines(recent.iter().chain(&after), i, self.line_offset) {
+                        log::error!("{}", line);
+                    }
+                }
+            }
+            done = true;
+            None
+        })
+    }
+
+    /// Counts the lines matching `re`, respecting --ignore and stopping at the first empty line.
+    fn count_matching(self, it: impl Iterator<Item=(usize, String)>, re: &Regex) -> usize {
+        it.skip(self.ignore)
+        .take_while(|(_, line)| !line.is_empty())
+        .filter(|(_, line)| re.is_match(line))
+        .count()
+    }
+
+    /// The number of tokens on each line for --count-tokens, leaving out ignored lines and
+    /// stopping at an empty one.
+    fn token_counts(self, it: impl Iterator<Item=(usize, String)>) -> impl Iterator<Item=usize> {
+        it.filter(move |(i, _)| *i >= self.ignore)
+            .take_while(|(_, line)| !line.is_empty())
+            .map(move |(_, line)| self.fields(&line).len())
+    }
+
+    /// Passes lines through, checking each has `expected` tokens for --expect-tokens. Lines
+    /// with more or fewer are warned about, or fail the run under --strict.
+    fn check_tokens(self, it: impl Iterator<Item=(usize, String)>, expected: usize) -> impl Iterator<Item=(usize, String)> {
+        it.map_while(move |(i, line)| {
+            if i < self.ignore || line.is_empty() {
+                return Some((i, line))
+            }
+            let count = self.fields(&line).len();
+            if count != expected {
+                let msg = format!("Line {} has {} tokens, expected {}", self.line_number(i), count, expected);
+                match self.strict {
+                    true => {
+                        log::error!("{}", msg);
+                        abort();
+                        return None
+                    },
+                    false => log::warn!("{}", msg)
+                }
+            }
+            Some((i, line))
+        })
+    }
+
+    /// Passes lines through, recording in `span` the timestamps in `column`. Lines with an invalid
+    /// timestamp stop the input, or are left out under --silent.
+    fn track_span<'a>(self, it: impl Iterator<Item=(usize, String)> + 'a, column: usize, span: &'a timestamp::Span) -> impl Iterator<Item=(usize, String)> + 'a {
+        it.filter_map(move |(i, line)| {
+            if i < self.ignore || line.is_empty() {
+                return Some(Some((i, line)))
+            }
+            match field(&line, column).and_then(timestamp::seconds) {
+                Some(seconds) => {
+                    span.record(seconds);
+                    Some(Some((i, line)))
+                },
+                None if self.silent => {
+                    log::warn!("Ignoring invalid timestamp at line {}", self.line_number(i));
+                    None
+                },
+                None => {
+                    log::error!("Invalid timestamp at line {}", self.line_number(i));
+                    Some(None)
+                }
+            }
+        })
+        .map_while(|line| line)
+    }
+
+    /// Splits every line into the fields in the `first` to `last` columns, each paired with the
+    /// index of its line. Ignored and empty lines pass through whole.
+    fn split_columns(self, it: impl Iterator<Item=(usize, String)>, (first, last): (usize, usize)) -> impl Iterator<Item=(usize, String)> {
+        it.map_while(move |(i, line)| {
+            if i < self.ignore || line.is_empty() {
+                return Some(vec![(i, line)])
+            }
+            let fields: Vec<(usize, String)> = (first..=last)
+                .filter_map(|c| field(&line, c))
+                .map(|f| (i, f.to_string()))
+                .collect();
+            if fields.len() < last - first + 1 {
+                let msg = format!("Missing some of columns {}-{} at line {}", first, last, self.line_number(i));
+                match self.silent {
+                    true => log::warn!("{}", msg),
+                    false => {
+                        log::error!("{}", msg);
+                        return None
+                    }
+                }
+            }
+            Some(fields)
+        })
+        .flatten()
+    }
+
+    /// Passes lines through, recording in `most` the most decimal places of any valid value seen.
+    fn track_decimals<'a>(self, it: impl Iterator<Item=(usize, String)> + 'a, most: &'a Cell<usize>) -> impl Iterator<Item=(usize, String)> + 'a {
+        it.inspect(move |(i, line)| {
+            if let Some(d) = self.decimals(*i, line) {
+                most.set(most.get().max(d));
+            }
+        })
+    }
+
+    /// Like `parse_input`, but pairs each value with the field in the `key` column of its line.
+    /// Ignored lines are dropped rather than becoming the identity, so headers don't form a group
+    fn parse_keyed(self, key: usize, it: impl Iterator<Item=(usize, String)>) -> impl Iterator<Item=(String, f64)> {
+        it.filter(move |(i, _)| *i >= self.ignore)
+        .map(move |(i, line)| {
+            let k = self.field(&line, key).unwrap_or_default().into_owned();
+            self.handle(i, &line).map(|v| v.map(|v| (k, v)))
+        })
+        .map_while(unwrap_handled)
+    }
+
+    /// Reads a single value, as a float or as a duration with --input-as-duration.
+    fn parse(self, val: &str) -> Result<f64, String> {
+        let val = &*self.delocalize(val);
+        if self.bool_as_number {
+            if let Some(b) = boolean(val) {
+                return Ok(b)
+            }
+        }
+        if self.no_scientific && val.contains(['e', 'E']) {
+            return Err(format!("{} is in scientific notation", val))
+        }
+        match (self.duration, self.ignore_case) {
+            (true, true) => duration::parse(&val.to_ascii_lowercase()).map(|seconds| self.bits.round(seconds)),
+            (true, false) => duration::parse(val).map(|seconds| self.bits.round(seconds)),
+            (false, _) => self.bits.parse(val)
+        }
+    }
+
+    /// A number written in the --input-locale, rewritten with a `.` decimal point and no grouping.
+    fn delocalize<'a>(self, val: &'a str) -> Cow<'a, str> {
+        match self.locale {
+            Some(locale) => locale.read(val).into(),
+            None => val.into()
+        }
+    }
+
+    /// Under --strict-finite a NaN or infinite value is an error, no matter --silent, and main
+    /// ends the whole run on it.
+    fn check_finite(self, i: usize, val: f64) -> Result<(), String> {
+        match self.strict_finite && !val.is_finite() {
+            true => Err(format!("Non-finite value {} at line {}", val, self.line_number(i))),
+            false => Ok(())
+        }
+    }
+
+    /// Applies the per value transforms the user asked for to a successfully parsed value.
+    fn transform(self, val: f64) -> Result<f64, String> {
+        let val = match self.negate {
+            true => -val,
+            false => val
+        };
+        let val = match self.reciprocal {
+            true if val == 0. => return Err("Can't take the reciprocal of 0".to_string()),
+            true => 1. / val,
+            false => val
+        };
+        let val = match self.log {
+            true if val <= 0. => return Err(format!("Can't take the log of {}", val)),
+            true => val.ln(),
+            false => val
+        };
+        let val = match self.exp {
+            true => val.exp(),
+            false => val
+        };
+        let val = match self.round_to_multiple {
+            Some(m) => (val / m).round() * m,
+            None => val
+        };
+        // counting turns every value into a 1 or a 0 to be added up
+        if let Some(bounds) = self.count_between {
+            return Ok(match bounds.contains(val) {
+                true => 1.,
+                false => 0.
+            })
+        }
+        Ok(match (self.count_nonzero, self.square) {
+            (Some(epsilon), _) if val.abs() > epsilon => 1.,
+            (Some(_), _) => 0.,
+            (None, true) => val * val,
+            (None, false) => val
+        })
+    }
+
+    /// Handles a value and its index according to the flags specified by the user.
+    fn handle(self, i: usize, val: &str) -> Result<Option<f64>, String> {
+        if i < self.ignore {
+            if val.is_empty() {
+                let msg = format!("Empty line {} is among the {} ignored lines, --ignore may be skipping data",
+                    self.line_number(i), self.ignore);
+                match self.strict {
+                    true => {
+                        abort();
+                        return Err(msg)
+                    },
+                    false => log::warn!("{}", msg)
+                }
+            }
+            log::debug!("Ignored value {}, using {}", val, self.logged(self.identity));
+            return Ok(Some(self.identity))
+        }
+        if val.is_empty() {
+            if let Some(v) = self.empty_as {
+                log::debug!("Found empty at line number {}, using {}", self.line_number(i), self.logged(v));
+                return Ok(Some(self.bits.round(v)))
+            }
+            log::debug!("Found empty at line number {}, exiting.", self.line_number(i));
+            return Ok(None)
+        }
+        let multiplier = match self.multiplier_column {
+            Some(c) => match self.field(val, c) {
+                Some(m) => match self.parse(&m) {
+                    Ok(m) => m,
+                    Err(_) => return self.recover(i, format!("Invalid multiplier {}", m))
+                },
+                None => return self.recover(i, format!("Missing column {}", c))
+            },
+            None => 1.
+        };
+        let val = match self.column {
+            Some(c) => match self.field(val, c) {
+                Some(f) => f,
+                None => return self.recover(i, format!("Missing column {}", c))
+            },
+            None => val.into()
+        };
+        let parsed = self.parse(&val);
+        if let Ok(v) = parsed {
+            if let Err(e) = self.check_finite(i, v) {
+                abort();
+                return Err(e)
+            }
+        }
+        match parsed {
+            Ok(v) => match self.transform(v * multiplier) {
+                Ok(v) => {
+                    let v = self.bits.round(v);
+                    log::debug!("Parsed {} at line {}", self.logged(v), self.line_number(i));
+                    Ok(Some(v))
+                },
+                Err(e) => self.recover(i, e)
+            },
+            Err(e) => {
+                match (self.on_error_value, self.silent) {
+                    (Some(v), _) => {
+                        log::warn!("Using {} for {} at line {}: {}", self.logged(v), val, self.line_number(i), e);
+                        Ok(Some(self.bits.round(v)))
+                    },
+                    (None, true) => {
+                        log::warn!("Ignoring parse error {} for {} at line {}", e, val, self.line_number(i));
+                        Ok(Some(self.identity))
+                    },
+                    (None, false) => {
+                        log::debug!("{}", e);
+                        Err(format!("Failed to parse {} at line {}", val, self.line_number(i)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the rows of a matrix into its columns for --transpose. Ignored lines are dropped
+    /// rather than becoming the identity, so headers don't become a row; an empty line ends the
+    /// matrix. Rows shorter than the widest are filled with `pad`, or are an error without it.
+    fn transpose(self, it: impl Iterator<Item=(usize, String)>, pad: Option<f64>, max: Option<usize>) -> Result<Vec<Vec<f64>>, String> {
+        let mut rows = Vec::new();
+        let mut buffered = 0;
+        for (i, line) in it.filter(|(i, _)| *i >= self.ignore) {
+            if line.is_empty() {
+                break
+            }
+            let mut row = Vec::new();
+            for field in self.fields(&line) {
+                if let Some(v) = self.handle(i, &field)? {
+                    row.push(v);
+                }
+            }
+            buffered += row.len();
+            if let Some(max) = max.filter(|max| buffered > *max) {
+                return Err(too_many_buffered(max))
+            }
+            rows.push((i, row));
+        }
+        let width = rows.iter().map(|(_, row)| row.len()).max().unwrap_or(0);
+        for (i, row) in &mut rows {
+            if row.len() < width {
+                match pad {
+                    Some(v) => row.resize(width, self.bits.round(v)),
+                    None => return Err(format!("Line {} has {} of the {} columns", self.line_number(*i), row.len(), width))
+                }
+            }
+        }
+        Ok((0..width).map(|c| rows.iter().map(|(_, row)| row[c]).collect()).collect())
+    }
+
+    /// Folds every column of the lines on its own for --per-field, leaving out ignored lines and
+    /// stopping at an empty one. The first line sets the number of columns; other lines are an
+    /// error, or under --silent add to the columns they have.
+    fn fold_fields(self, it: impl Iterator<Item=(usize, String)>, operator: fn(f64, f64) -> f64, start: Option<f64>) -> Result<Vec<f64>, String> {
+        let mut results: Vec<Option<f64>> = Vec::new();
+        let mut width = None;
+        for (i, line) in it.filter(|(i, _)| *i >= self.ignore) {
+            if line.is_empty() {
+                break
+            }
+            let fields = self.fields(&line);
+            let expected = *width.get_or_insert(fields.len());
+            if fields.len() != expected {
+                let msg = format!("Line {} has {} of the {} columns", self.line_number(i), fields.len(), expected);
+                match self.silent {
+                    true => log::warn!("{}", msg),
+                    false => return Err(msg)
+                }
+            }
+            for (c, field) in fields.iter().enumerate() {
+                if c == results.len() {
+                    results.push(start);
+                }
+                if let Some(v) = self.handle(i, field)? {
+                    results[c] = Some(results[c].map_or(v, |acc| operator(acc, v)));
+                }
+            }
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// All the fields of `line`, split on whitespace or as CSV with --csv, leaving out empty CSV
+    /// fields under --skip-empty-tokens.
+    fn fields<'a>(self, line: &'a str) -> Vec<Cow<'a, str>> {
+        match self.csv {
+            true => csv_fields(line).unwrap_or_default().into_iter()
+                .filter(|field| !self.skip_empty_tokens || !field.is_empty())
+                .map(Cow::Owned)
+                .collect(),
+            false => line.split_whitespace().map(Cow::Borrowed).collect()
+        }
+    }
+
+    /// The `column`th field of `line`, starting at 1, counted like `fields`.
+    fn field<'a>(self, line: &'a str, column: usize) -> Option<Cow<'a, str>> {
+        match self.csv {
+            true if self.skip_empty_tokens => self.fields(line).into_iter().nth(column.checked_sub(1)?),
+            true => csv_field(line, column).map(Cow::Owned),
+            false => field(line, column).map(Cow::Borrowed)
+        }
+    }
+
+    /// The number of decimal places written in the value of this line, if it has a valid one.
+    fn decimals(self, i: usize, line: &str) -> Option<usize> {
+        if i < self.ignore {
+            return None
+        }
+        let val = match self.column {
+            Some(c) => self.field(line, c)?,
+            None => line.into()
+        };
+        let val = self.delocalize(&val);
+        val.parse::<f64>().ok()?;
+        let mantissa = val.split(['e', 'E']).next()?;
+        Some(mantissa.find('.').map_or(0, |dot| mantissa.len() - dot - 1))
+    }
+
+    /// The line number to report for the line with index `i`.
+    fn line_number(self, i: usize) -> usize {
+        i + self.line_offset
+    }
+
+    /// `v` as it's written in log messages, to the --log-precision.
+    fn logged(self, v: f64) -> String {
+        match self.log_precision {
+            Some(precision) => format!("{:.*}", precision, v),
+            None => self.bits.display(v)
+        }
+    }
+
+    /// Substitutes the --on-error-value, or the identity under --silent, for a bad value,
+    /// otherwise fails with `msg`.
+    fn recover(self, i: usize, msg: String) -> Result<Option<f64>, String> {
+        match (self.on_error_value, self.silent) {
+            (Some(v), _) => {
+                log::warn!("Using {} for {} at line {}", self.logged(v), msg, self.line_number(i));
+                Ok(Some(self.bits.round(v)))
+            },
+            (None, true) => {
+                log::warn!("Ignoring {} at line {}", msg, self.line_number(i));
+                Ok(Some(self.identity))
+            },
+            (None, false) => Err(format!("{} at line {}", msg, self.line_number(i)))
+        }
+    }
+}
+
+/// Look up an encoding by its label, as used for --input-encoding.
+fn encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or(format!("Unknown encoding {}", label))
+}
+
+/// Decode `line` from base64 for --base64, into the text it holds.
+fn decode_base64(line: &str) -> Result<String, String> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(line).map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|_| "the decoded bytes aren't UTF-8".to_string())
+}
+
+/// Remove ANSI escape sequences from `line`: control sequences like the `ESC[1;31m` of colors,
+/// up to their final letter, and the short escapes.
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('[') => {
+                chars.by_ref().find(|c| ('\x40'..='\x7e').contains(c));
+            },
+            // character set escapes like `ESC(B` have one more character
+            Some('\x20'..='\x2f') => {
+                chars.next();
+            },
+            _ => ()
+        }
+    }
+    stripped
+}
+
+/// Remove the spaces grouping digits in `line`, like in 1 234 567. A space, or the no-break
+/// spaces some locales use, only groups digits when it has a digit before it and exactly three after.
+fn join_digit_groups(line: &str) -> String {
+    join_groups(line, &[' ', '\u{a0}', '\u{202f}'])
+}
+
+/// Remove any of `separators` grouping digits in `line`, as for `join_digit_groups`.
+fn join_groups(line: &str, separators: &[char]) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let digit = |i: usize| chars.get(i).is_some_and(char::is_ascii_digit);
+    chars.iter()
+        .enumerate()
+        .filter(|(i, c)| {
+            let groups = separators.contains(c)
+                && *i > 0 && digit(i - 1)
+                && (i + 1..=i + 3).all(digit) && !digit(i + 4);
+            !groups
+        })
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// The `column`th field of `line` read as a CSV record, starting at 1, with any commas grouping
+/// its digits removed. Fields are trimmed, like whitespace separated ones.
+fn csv_field(line: &str, column: usize) -> Option<String> {
+    csv_fields(line)?.into_iter().nth(column.checked_sub(1)?)
+}
+
+/// All the fields of `line` as CSV, like `csv_field`.
+fn csv_fields(line: &str) -> Option<Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(line.as_bytes());
+    let record = reader.records().next()?.ok()?;
+    Some(record.iter().map(|field| join_groups(field, &[','])).collect())
+}
+
+/// Read a boolean literal as 1 or 0 for --bool-as-number.
+fn boolean(val: &str) -> Option<f64> {
+    match val.to_ascii_lowercase().as_str() {
+        "true" | "yes" => Some(1.),
+        "false" | "no" => Some(0.),
+        _ => None
+    }
+}
+
+/// Read the character for --output-delimiter, by name or as itself.
+fn delimiter(s: &str) -> Result<char, String> {
+    match s {
+        "tab" => Ok('\t'),
+        "comma" => Ok(','),
+        "space" => Ok(' '),
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("Delimiter {} isn't a single character, tab, comma or space", s))
+            }
+        }
+    }
+}
+
+/// Parse the percentage for trimmed-mean, which has to leave some values in the middle.
+fn trim_percent(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| e.to_string())? {
+        p if (0. ..50.).contains(&p) => Ok(p),
+        p => Err(format!("Can't trim {}% from each end, it has to be at least 0 and less than 50", p))
+    }
+}
+
+/// Parse the duration for rate, which has to be a positive number of seconds.
+fn seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| e.to_string())? {
+        d if d > 0. && d.is_finite() => Ok(d),
+        d => Err(format!("Can't take a rate over {} seconds", d))
+    }
+}
+
+/// Parse the probability for --sample-rate, from 0 to 1.
+fn probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| e.to_string())? {
+        p if (0. ..=1.).contains(&p) => Ok(p),
+        p => Err(format!("Can't sample at a rate of {}, it has to be from 0 to 1", p))
+    }
+}
+
+/// Parse the columns for --columns, like 2-5, or 3 for just the one.
+fn column_range(s: &str) -> Result<(usize, usize), String> {
+    let (first, last) = s.split_once('-').unwrap_or((s, s));
+    let first = first.trim().parse::<usize>().map_err(|e| e.to_string())?;
+    let last = last.trim().parse::<usize>().map_err(|e| e.to_string())?;
+    match (first, last) {
+        (0, _) => Err("Columns start at 1".to_string()),
+        (first, last) if first > last => Err(format!("Column range {} runs backwards", s)),
+        range => Ok(range)
+    }
+}
+
+/// Parse the size for --running-window-stats, which can't be 0.
+fn window_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>().map_err(|e| e.to_string())? {
+        0 => Err("The window needs at least one value".to_string()),
+        size => Ok(size)
+    }
+}
+
+/// Parse the multiple for --round-to-multiple, which can't be 0.
+fn multiple(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| e.to_string())? {
+        m if m == 0. || !m.is_finite() => Err(format!("Can't round to multiples of {}", m)),
+        m => Ok(m)
+    }
+}
+
+/// The whitespace separated field in `column` of `line`, counting from 1.
+fn field(line: &str, column: usize) -> Option<&str> {
+    line.split_whitespace().nth(column.checked_sub(1)?)
+}
+
+/// Number `lines` like a compiler error, from `offset`, pointing at the line with index `failing`.
+fn context_lines<'a>(lines: impl Iterator<Item=&'a (usize, String)>, failing: usize, offset: usize) -> Vec<String> {
+    let lines: Vec<&(usize, String)> = lines.collect();
+    let width = lines.last().map_or(1, |(i, _)| (i + offset).to_string().len());
+    lines.into_iter()
+        .map(|(i, line)| {
+            let marker = if *i == failing { '>' } else { ' ' };
+            format!("{} {:>width$} | {}", marker, i + offset, line, width = width)
+        })
+        .collect()
+}
+
+/// Unwraps the result of `InputHandler::handle`, logging and stopping the stream on errors.
+fn unwrap_handled<T>(val: Result<Option<T>, String>) -> Option<T> {
+    match val {
+        Ok(v) => v,
+        Err(e) => {
+            // We only get here if --silent is false (which is the default)
+            log::error!("{}", e);
+            None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Bounds, FloatBits, Input, InputHandler, Opts, Regex, SubCommand, apply_then, buffer, cache_args, cache_dir, column_range, compare, context_lines, csv_field, decode_base64, delimiter, encoding, enough_values, exit_code, expand_glob, field, fold, fold_each, fold_showing_steps, fold_with_subtotals, inputs, join_digit_groups, marked_reader, multiple, nonzero, open, rate, read_seed, reader, resolve_operation, skip_zeros, strip_ansi, summarize, weighted_mean, write_result};
+    use super::format::{LineEnding, OutputFormatter};
+    use super::merge::MergeSorted;
+    use clap::Clap;
+    use std::cell::Cell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    fn handler(silent: bool) -> InputHandler {
+        InputHandler {
+            ignore: 2,
+            silent,
+            negate: false,
+            reciprocal: false,
+            log: false,
+            exp: false,
+            round_to_multiple: None,
+            column: None,
+            multiplier_column: None,
+            csv: false,
+            skip_empty_tokens: false,
+            encoding: encoding_rs::UTF_8,
+            duration: false,
+            ignore_case: false,
+            no_scientific: false,
+            log_precision: None,
+            empty_as: None,
+            count_nonzero: None,
+            count_between: None,
+            square: false,
+            strict_finite: false,
+            bits: FloatBits::Single,
+            line_offset: 1,
+            bool_as_number: false,
+            strip_ansi: false,
+            base64: false,
+            on_error_value: None,
+            strict: false,
+            space_grouped: false,
+            locale: None,
+            skip_blank: false,
+            identity: 1.5
+        }
+    }
+
+    #[test]
+    fn test_trim_lines() {
+        let handler = handler(false);
+        for (k, v) in handler.clean_and_enumerate(b"\t\t1\n2\t\t\n   3   \n" as &[u8]) {
+            match k {
+                0 => assert_eq!("1", v),
+                1 => assert_eq!("2", v),
+                2 => assert_eq!("3", v),
+                _ => panic!()
+            }
+        }
+    }
+
+    #[test]
+    fn test_successful_handle() {
+        let handler = handler(false);
+        assert_eq!(Ok(Some(3.0)), handler.handle(2, "3.0"));
+    }
+ 
+    #[test]
+    fn test_ignore_returns_identity() {
+        let handler = handler(false);
+        assert_eq!(Ok(Some(1.5)), handler.handle(0, "2.0"));
+        assert_eq!(Ok(Some(1.5)), handler.handle(1, "2.0"));
+        assert_eq!(Ok(Some(3.0)), handler.handle(2, "3.0"));
+    }
+
+    #[test]
+    fn test_exit_on_empty_line() {
+        let handler = handler(false);
+        assert_eq!(Ok(None), handler.handle(2, ""));
+    }
+
+    #[test]
+    fn test_exit_on_parse_error() {
+        let handler = handler(false);
+        let input_string = "notf32";
+        let msg = format!("Failed to parse {} at line {}", &input_string, 3);
+        assert_eq!(Err(msg), handler.handle(2, input_string));
+    }
+
+    #[test]
     fn test_ignore_parse_error() {
         let handler = handler(true);
         let input_string = "notf32";
         assert_eq!(Ok(Some(1.5)), handler.handle(2, input_string));
     }
+
+    #[test]
+    fn test_merge_sorted_fixtures() {
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let fixtures: [&[u8]; 3] = [b"a\n1\n4\n7\n", b"b\n2\n5\n\n99\n", b"c\n3\n6\n8\n"];
+        let line = Cell::new(0);
+        let sources = fixtures.iter()
+            .map(|f| handler.parse_input(handler.clean_and_enumerate(*f).inspect(|(i, _)| line.set(*i)))
+                .filter(|_| line.get() >= handler.ignore))
+            .collect();
+        // each header is dropped, and each file stops at its own blank line
+        let merged: Vec<f64> = MergeSorted::new(sources).collect();
+        assert_eq!(vec![1., 2., 3., 4., 5., 6., 7., 8.], merged);
+    }
+
+    fn resolve(args: &[&str], env_op: Option<&str>) -> Result<Opts, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        resolve_operation(Opts::parse_from(&args), args, env_op.map(String::from))
+    }
+
+    #[test]
+    fn test_operation_from_cli() {
+        let opts = resolve(&["mathcli", "add"], None).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::Add)));
+    }
+
+    #[test]
+    fn test_operation_from_env() {
+        let opts = resolve(&["mathcli", "--ignore", "1"], Some("mul")).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::Mul { skip_zeros: false })));
+        assert_eq!(1, opts.ignore);
+    }
+
+    #[test]
+    fn test_cli_operation_wins_over_env() {
+        let opts = resolve(&["mathcli", "sub"], Some("mul")).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::Sub)));
+    }
+
+    #[test]
+    fn test_count_between_bounds_in_order() {
+        assert!(resolve(&["mathcli", "count-between", "1", "1"], None).is_ok());
+        let reversed = Err("count-between's low bound 5 is above its high bound -1".to_string());
+        assert_eq!(reversed, resolve(&["mathcli", "count-between", "5", "-1"], None).map(|_| ()));
+        assert_eq!(reversed, resolve(&["mathcli"], Some("count-between 5 -1")).map(|_| ()));
+    }
+
+    #[test]
+    fn test_missing_operation() {
+        assert!(resolve(&["mathcli"], None).is_err());
+        assert!(resolve(&["mathcli"], Some("pow")).is_err());
+    }
+
+    #[test]
+    fn test_negate() {
+        let handler = InputHandler { negate: true, ..handler(false) };
+        // ignored lines keep the identity, only parsed values are negated
+        assert_eq!(Ok(Some(1.5)), handler.handle(0, "2.0"));
+        assert_eq!(Ok(Some(-3.0)), handler.handle(2, "3.0"));
+        assert_eq!(Ok(Some(4.0)), handler.handle(2, "-4.0"));
+    }
+
+    #[test]
+    fn test_add_negate_is_negated_sum() {
+        let handler = InputHandler { ignore: 0, negate: true, ..handler(false) };
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(b"1\n2\n3.5\n" as &[u8])).sum();
+        assert_eq!(-6.5, sum);
+    }
+
+    #[test]
+    fn test_reciprocal() {
+        let handler = InputHandler { reciprocal: true, ..handler(false) };
+        assert_eq!(Ok(Some(0.25)), handler.handle(2, "4"));
+        assert_eq!(Ok(Some(-2.0)), handler.handle(2, "-0.5"));
+        assert_eq!(Err("Can't take the reciprocal of 0 at line 3".to_string()), handler.handle(2, "0"));
+    }
+
+    #[test]
+    fn test_reciprocal_zero_silent() {
+        let handler = InputHandler { ignore: 0, reciprocal: true, identity: 0., ..handler(true) };
+        // harmonic sum 1/1 + 1/2 + 1/4, the 0 contributes the identity
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(b"1\n2\n0\n4\n" as &[u8])).sum();
+        assert_eq!(1.75, sum);
+    }
+
+    #[test]
+    fn test_field() {
+        assert_eq!(Some("b"), field("a  b\tc", 2));
+        assert_eq!(None, field("a b", 3));
+        assert_eq!(None, field("a b", 0));
+    }
+
+    #[test]
+    fn test_column() {
+        let strict = InputHandler { column: Some(2), ..handler(false) };
+        assert_eq!(Ok(Some(3.0)), strict.handle(2, "x 3.0 y"));
+        assert_eq!(Err("Missing column 2 at line 3".to_string()), strict.handle(2, "x"));
+        let silent = InputHandler { column: Some(2), ..handler(true) };
+        assert_eq!(Ok(Some(1.5)), silent.handle(2, "x"));
+    }
+
+    #[test]
+    fn test_parse_keyed_skips_ignored() {
+        let handler = InputHandler { ignore: 1, column: Some(2), ..handler(false) };
+        let keyed: Vec<(String, f64)> = handler.parse_keyed(1, handler.clean_and_enumerate(b"key value\na 1\nb 2\na 3\n\nc 4\n" as &[u8])).collect();
+        assert_eq!(vec![("a".to_string(), 1.), ("b".to_string(), 2.), ("a".to_string(), 3.)], keyed);
+    }
+
+    #[test]
+    fn test_seed_add_from_file() {
+        let path = std::env::temp_dir().join("mathcli_test_seed_add_from_file");
+        std::fs::write(&path, "10.5\n").unwrap();
+        let seed = read_seed(&path).unwrap();
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let total = handler.parse_input(handler.clean_and_enumerate(b"1\n2\n" as &[u8])).fold(seed, std::ops::Add::add);
+        assert_eq!(13.5, total);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_seed_invalid_or_missing() {
+        let path = std::env::temp_dir().join("mathcli_test_seed_invalid_or_missing");
+        std::fs::write(&path, "total: 10\n").unwrap();
+        assert!(read_seed(&path).unwrap_err().starts_with("Failed to parse seed total: 10"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_seed(&path).unwrap_err().starts_with("Failed to read seed"));
+    }
+
+    #[test]
+    fn test_latin1_input() {
+        let handler = InputHandler { ignore: 0, column: Some(2), encoding: encoding("latin1").unwrap(), ..handler(false) };
+        let keyed: Vec<(String, f64)> = handler.parse_keyed(1, handler.clean_and_enumerate(b"caf\xe9 2\r\nna\xefve 3\n" as &[u8])).collect();
+        assert_eq!(vec![("caf\u{e9}".to_string(), 2.), ("na\u{ef}ve".to_string(), 3.)], keyed);
+    }
+
+    #[test]
+    fn test_invalid_utf8_input() {
+        let strict = InputHandler { ignore: 0, ..handler(false) };
+        let lines: Vec<(usize, String)> = strict.clean_and_enumerate(b"1\n\xe9\n2\n" as &[u8]).collect();
+        assert_eq!(vec![(0, "1".to_string())], lines);
+        // under --silent the bad line gets through and fails to parse instead
+        let silent = InputHandler { ignore: 0, identity: 0., ..handler(true) };
+        let sum: f64 = silent.parse_input(silent.clean_and_enumerate(b"1\n\xe9\n2\n" as &[u8])).sum();
+        assert_eq!(3., sum);
+    }
+
+    #[test]
+    fn test_unknown_encoding() {
+        assert!(encoding("utf-8").is_ok());
+        assert_eq!(Err("Unknown encoding klingon".to_string()), encoding("klingon").map(|e| e.name()));
+    }
+
+    #[test]
+    fn test_log() {
+        let handler = InputHandler { log: true, ..handler(false) };
+        assert_eq!(Ok(Some(0.)), handler.handle(2, "1"));
+        assert_eq!(Err("Can't take the log of 0 at line 3".to_string()), handler.handle(2, "0"));
+        assert_eq!(Err("Can't take the log of -2 at line 3".to_string()), handler.handle(2, "-2"));
+    }
+
+    #[test]
+    fn test_log_then_exp_round_trips() {
+        let handler = InputHandler { log: true, exp: true, ..handler(false) };
+        for val in &[0.5f64, 1., 2., 1234.5] {
+            let round_trip = handler.handle(2, &val.to_string()).unwrap().unwrap();
+            assert!((round_trip - val).abs() <= val * 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_exp_then_add() {
+        let handler = InputHandler { ignore: 0, exp: true, ..handler(false) };
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(b"0\n0\n" as &[u8])).sum();
+        assert_eq!(2., sum);
+    }
+
+    #[test]
+    fn test_decimals() {
+        let handler = handler(false);
+        assert_eq!(None, handler.decimals(0, "1.25"));
+        assert_eq!(Some(2), handler.decimals(2, "1.25"));
+        assert_eq!(Some(0), handler.decimals(2, "-3"));
+        assert_eq!(Some(1), handler.decimals(2, "2.5e3"));
+        assert_eq!(None, handler.decimals(2, "1.2.3"));
+    }
+
+    #[test]
+    fn test_track_decimals() {
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(true) };
+        let most = Cell::new(0);
+        let input = b"1.23456\n1.5\n2.125\nbad.0000001\n3\n" as &[u8];
+        let sum: f64 = handler.parse_input(handler.track_decimals(handler.clean_and_enumerate(input), &most)).sum();
+        assert_eq!(6.625, sum);
+        assert_eq!(3, most.get());
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let errors = Cell::new(0);
+        let input = b"1\nx\n2\n\t\n3\ny\nz\n4\n\n5\n" as &[u8];
+        let values: Vec<f64> = handler.parse_collecting_errors(handler.clean_and_enumerate(input), &errors).collect();
+        assert_eq!(vec![1., 2.], values);
+        assert_eq!(1, errors.get());
+        let errors = Cell::new(0);
+        let input = b"1\nx\n2\n3\ny\nz\n4\n\n5\n" as &[u8];
+        let values: Vec<f64> = handler.parse_collecting_errors(handler.clean_and_enumerate(input), &errors).collect();
+        assert_eq!(vec![1., 2., 3., 4.], values);
+        assert_eq!(3, errors.get());
+    }
+
+    #[test]
+    fn test_input_as_duration() {
+        let strict = InputHandler { duration: true, ..handler(false) };
+        assert_eq!(Ok(Some(3600.)), strict.handle(2, "1h"));
+        assert_eq!(Ok(Some(90.)), strict.handle(2, "90s"));
+        assert_eq!(Ok(Some(0.25)), strict.handle(2, "250ms"));
+        assert_eq!(Err("Failed to parse 5x at line 3".to_string()), strict.handle(2, "5x"));
+        let silent = InputHandler { duration: true, ..handler(true) };
+        assert_eq!(Ok(Some(1.5)), silent.handle(2, "5x"));
+    }
+
+    #[test]
+    fn test_min_values_boundary() {
+        assert_eq!(Ok(()), enough_values(0, 0));
+        assert_eq!(Ok(()), enough_values(3, 3));
+        assert_eq!(Err("Only 2 values were read, at least 3 are needed".to_string()), enough_values(2, 3));
+    }
+
+    #[test]
+    fn test_max_buffer_count() {
+        assert_eq!(Ok(vec![1., 2.]), buffer(&mut vec![1., 2.].into_iter(), Some(2)));
+        assert_eq!(Ok(vec![1., 2.]), buffer(&mut vec![1., 2.].into_iter(), None));
+        assert_eq!(Err("More than 2 values to hold in memory, --max-buffer-count may need raising".to_string()),
+            buffer(&mut (1..).map(f64::from), Some(2)));
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let input = b"1 2\n3 4\n5 6\n" as &[u8];
+        assert!(handler.transpose(handler.clean_and_enumerate(input), None, Some(6)).is_ok());
+        assert!(handler.transpose(handler.clean_and_enumerate(input), None, Some(5)).is_err());
+    }
+
+    #[test]
+    fn test_min_values_skips_ignored() {
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let (line, count) = (Cell::new(0), Cell::new(0));
+        let lines = handler.clean_and_enumerate(b"hdr\n1\n3\n" as &[u8]).inspect(|(i, _)| line.set(*i));
+        handler.parse_input(lines).filter(|_| line.get() >= handler.ignore).for_each(|_| count.set(count.get() + 1));
+        assert_eq!(2, count.get());
+        assert!(enough_values(count.get(), 3).is_err());
+    }
+
+    #[test]
+    fn test_input_order() {
+        let file = |name: &str| Input::File(name.into());
+        let order = |args: &[&str]| inputs(&resolve(args, None).unwrap()).unwrap();
+        assert_eq!(vec![Input::Stdin], order(&["mathcli", "sub"]));
+        assert_eq!(vec![file("a"), file("b")], order(&["mathcli", "-f", "a", "-f", "b", "sub"]));
+        assert_eq!(vec![Input::Stdin, file("a"), file("b")], order(&["mathcli", "-f", "a", "-f", "b", "--stdin-first", "sub"]));
+        assert_eq!(vec![file("a"), file("b"), Input::Stdin], order(&["mathcli", "-f", "a", "-f", "b", "--files-first", "sub"]));
+        let command = Input::Command("echo 1".to_string());
+        assert_eq!(vec![file("a"), command], order(&["mathcli", "--exec", "echo 1", "-f", "a", "sub"]));
+    }
+
+    #[test]
+    fn test_show_steps_for_sub() {
+        let mut steps = Vec::new();
+        let result = fold_showing_steps(vec![10., 3., 2.].into_iter(), std::ops::Sub::sub, SubCommand::Sub.symbol(), FloatBits::Single, None, |step| steps.push(step));
+        assert_eq!(Some(5.), result);
+        assert_eq!(vec!["10 - 3 = 7", "7 - 2 = 5"], steps);
+    }
+
+    #[test]
+    fn test_show_steps_from_start() {
+        let mut steps = Vec::new();
+        let result = fold_showing_steps(vec![2.].into_iter(), std::ops::Mul::mul, '*', FloatBits::Single, Some(1.), |step| steps.push(step));
+        assert_eq!(Some(2.), result);
+        assert_eq!(vec!["1 * 2 = 2"], steps);
+    }
+
+    #[test]
+    fn test_empty_as() {
+        let handler = InputHandler { empty_as: Some(0.), ..handler(false) };
+        assert_eq!(Ok(Some(0.)), handler.handle(2, ""));
+        // ignored lines are still the identity
+        assert_eq!(Ok(Some(1.5)), handler.handle(0, ""));
+        let handler = InputHandler { ignore: 0, ..handler };
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(b"1\n\n  \n2\n" as &[u8])).sum();
+        assert_eq!(3., sum);
+    }
+
+    #[test]
+    fn test_round_to_multiple() {
+        let tens = InputHandler { round_to_multiple: Some(10.), ..handler(false) };
+        assert_eq!(Ok(Some(120.)), tens.handle(2, "123"));
+        assert_eq!(Ok(Some(130.)), tens.handle(2, "125"));
+        assert_eq!(Ok(Some(-10.)), tens.handle(2, "-7"));
+        let quarters = InputHandler { round_to_multiple: Some(0.25), ..handler(false) };
+        assert_eq!(Ok(Some(1.25)), quarters.handle(2, "1.3"));
+        assert_eq!(Ok(Some(1.5)), quarters.handle(2, "1.4"));
+    }
+
+    #[test]
+    fn test_multiple() {
+        assert_eq!(Ok(0.25), multiple("0.25"));
+        assert!(multiple("0").is_err());
+        assert!(multiple("inf").is_err());
+        assert!(multiple("ten").is_err());
+    }
+
+    #[test]
+    fn test_input_glob() {
+        let dir = std::env::temp_dir().join("mathcli_test_input_glob");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in &[("b.txt", "2\n"), ("a.txt", "1\n"), ("c.txt", "3\n"), ("skip.csv", "4\n")] {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        let pattern = dir.join("*.txt").display().to_string();
+        let files = expand_glob(&pattern).unwrap();
+        assert_eq!(vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("c.txt")], files);
+        let args = ["mathcli", "-f", "first", "--input-glob", &pattern, "sub"];
+        let mut expected = vec![Input::File("first".into())];
+        expected.extend(files.into_iter().map(Input::File));
+        assert_eq!(expected, inputs(&resolve(&args, None).unwrap()).unwrap());
+        let none = dir.join("*.json").display().to_string();
+        assert_eq!(Err(format!("No files match {}", none)), expand_glob(&none));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_count_nonzero() {
+        let handler = InputHandler { ignore: 1, identity: 0., count_nonzero: Some(0.), ..handler(false) };
+        let input = b"5\n0\n1.5\n-2\n0.0\n3\n" as &[u8];
+        let count: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(3., count);
+        let handler = InputHandler { count_nonzero: Some(0.1), ..handler };
+        let input = b"5\n0.05\n-0.05\n-0.5\n0.2\n" as &[u8];
+        let count: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(2., count);
+    }
+
+    #[test]
+    fn test_sum_of_squares() {
+        let handler = InputHandler { ignore: 1, identity: 0., square: true, ..handler(false) };
+        let input = b"10\n3\n-4\n0.5\n\n6\n" as &[u8];
+        let sum = handler.parse_input(handler.clean_and_enumerate(input)).reduce(SubCommand::SumOfSquares.operator(FloatBits::Single));
+        assert_eq!(Some(9. + 16. + 0.25), sum);
+        let opts = resolve(&["mathcli", "sum-of-squares"], None).unwrap();
+        assert!(InputHandler::new(&opts, 0.).square);
+    }
+
+    #[test]
+    fn test_count_between() {
+        let bounds = Bounds { low: 100., high: 200., exclusive_low: false, exclusive_high: false };
+        let handler = InputHandler { ignore: 1, identity: 0., count_between: Some(bounds), ..handler(false) };
+        let input = b"150\n99.5\n100\n150\n200\n200.5\n-150\n" as &[u8];
+        let count: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(3., count);
+        let handler = InputHandler { count_between: Some(Bounds { exclusive_low: true, ..bounds }), ..handler };
+        let count: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(2., count);
+        let handler = InputHandler { count_between: Some(Bounds { exclusive_low: true, exclusive_high: true, ..bounds }), ..handler };
+        let count: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(1., count);
+    }
+
+    #[test]
+    fn test_count_between_from_cli() {
+        let opts = resolve(&["mathcli", "count-between", "-5", "5", "--exclusive-high"], None).unwrap();
+        let bounds = Bounds { low: -5., high: 5., exclusive_low: false, exclusive_high: true };
+        assert_eq!(Some(bounds), InputHandler::new(&opts, 0.).count_between);
+    }
+
+    #[test]
+    fn test_count_nonzero_from_cli() {
+        let opts = resolve(&["mathcli", "count-nonzero", "--epsilon", "0.5"], None).unwrap();
+        assert_eq!(Some(0.5), InputHandler::new(&opts, 0.).count_nonzero);
+    }
+
+    #[test]
+    fn test_context_lines() {
+        let lines = [(7, "7".to_string()), (8, "8".to_string()), (9, "x".to_string()), (10, "10".to_string())];
+        let expected = vec!["   8 | 7", "   9 | 8", "> 10 | x", "  11 | 10"];
+        assert_eq!(expected, context_lines(lines.iter(), 9, 1));
+    }
+
+    #[test]
+    fn test_parse_with_context() {
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let input = b"1\n2\n3\nx\n4\n5\n6\n" as &[u8];
+        let values: Vec<f64> = handler.parse_with_context(handler.clean_and_enumerate(input), 2).collect();
+        assert_eq!(vec![1., 2., 3.], values);
+        let values: Vec<f64> = handler.parse_with_context(handler.clean_and_enumerate(b"1\n\n2\n" as &[u8]), 2).collect();
+        assert_eq!(vec![1.], values);
+    }
+
+    #[test]
+    fn test_strict_finite() {
+        let strict = InputHandler { strict_finite: true, ..handler(true) };
+        assert_eq!(Ok(()), strict.check_finite(2, 3.));
+        assert_eq!(Err("Non-finite value NaN at line 3".to_string()), strict.check_finite(2, "nan".parse().unwrap()));
+        assert_eq!(Err("Non-finite value inf at line 4".to_string()), strict.check_finite(3, "inf".parse().unwrap()));
+        assert_eq!(Err("Non-finite value -inf at line 5".to_string()), strict.check_finite(4, "-inf".parse().unwrap()));
+        let lenient = handler(true);
+        assert_eq!(Ok(()), lenient.check_finite(2, f64::NAN));
+        // an error even under --silent, for main to end the run on
+        assert_eq!(Err("Non-finite value inf at line 3".to_string()), strict.handle(2, "inf"));
+        assert!(super::aborted());
+        assert_eq!(Ok(Some(1.5)), strict.handle(2, "x"));
+    }
+
+    #[test]
+    fn test_strict_finite_stops_collecting_errors() {
+        let strict = InputHandler { ignore: 0, strict_finite: true, ..handler(false) };
+        let errors = Cell::new(0);
+        let values: Vec<f64> = strict.parse_collecting_errors(strict.clean_and_enumerate(b"1\nx\n2\nnan\n3\n" as &[u8]), &errors).collect();
+        assert_eq!(vec![1., 2.], values);
+        assert_eq!(2, errors.get());
+    }
+
+    #[test]
+    fn test_then_add_then_div() {
+        let opts = resolve(&["mathcli", "--then", "div", "--then-value", "100", "add"], None).unwrap();
+        assert_eq!(Ok(0.5), apply_then(50., &opts.then, &opts.then_value, FloatBits::Single));
+        let opts = resolve(&["mathcli", "--then", "div", "--then-value", "10", "--then", "sub", "--then-value", "-1", "add"], None).unwrap();
+        assert_eq!(Ok(6.), apply_then(50., &opts.then, &opts.then_value, FloatBits::Single));
+    }
+
+    #[test]
+    fn test_then_mismatched() {
+        assert!(apply_then(1., &[SubCommand::Add, SubCommand::Mul { skip_zeros: false }], &[1.], FloatBits::Single).is_err());
+        assert!("count-nonzero".parse::<SubCommand>().is_err());
+    }
+
+    #[test]
+    fn test_count_matching() {
+        let handler = InputHandler { ignore: 1, ..handler(false) };
+        let re = Regex::new("^ERROR").unwrap();
+        let input = b"ERROR header\nERROR one\nINFO two\nERROR three\nWARN ERROR\n\nERROR four\n" as &[u8];
+        assert_eq!(2, handler.count_matching(handler.clean_and_enumerate(input), &re));
+        let re = Regex::new("^[0-9]+$").unwrap();
+        assert_eq!(0, handler.count_matching(handler.clean_and_enumerate(b"x\na\nb\n" as &[u8]), &re));
+    }
+
+    #[test]
+    fn test_count_matching_from_cli() {
+        let opts = resolve(&["mathcli", "count-matching", "ERR(OR)?"], None).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::CountMatching { ref pattern }) if pattern == "ERR(OR)?"));
+    }
+
+    #[test]
+    fn test_float_bits_large_sum() {
+        let mut input = b"16777216\n".to_vec();
+        input.extend(b"1\n".repeat(10));
+        let sum = |bits| {
+            let handler = InputHandler { ignore: 0, bits, ..handler(false) };
+            handler.parse_input(handler.clean_and_enumerate(&input[..])).reduce(SubCommand::Add.operator(bits))
+        };
+        // every 1 is lost to rounding at 2^24 in an f32
+        assert_eq!(Some(16777216.), sum(FloatBits::Single));
+        assert_eq!(Some(16777226.), sum(FloatBits::Double));
+    }
+
+    #[test]
+    fn test_float_bits_parse() {
+        let single = InputHandler { ignore: 0, ..handler(false) };
+        let double = InputHandler { ignore: 0, bits: FloatBits::Double, ..handler(false) };
+        assert_eq!(Ok(Some(0.1f32 as f64)), single.handle(0, "0.1"));
+        assert_eq!(Ok(Some(0.1)), double.handle(0, "0.1"));
+        let opts = Opts::try_parse_from(["mathcli", "--float-bits", "64", "add"]).unwrap();
+        assert_eq!(FloatBits::Double, opts.float_bits);
+        assert!(Opts::try_parse_from(["mathcli", "--float-bits", "16", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_quiet_result() {
+        assert!(!Opts::try_parse_from(["mathcli", "add"]).unwrap().quiet_result);
+        let opts = Opts::try_parse_from(["mathcli", "--quiet-result", "--assert-monotonic", "inc", "add"]).unwrap();
+        assert!(opts.quiet_result);
+    }
+
+    #[test]
+    fn test_multiplier_column() {
+        let handler = InputHandler { ignore: 1, identity: 0., column: Some(2), multiplier_column: Some(3), ..handler(false) };
+        let input = b"item price quantity\napple 0.5 4\npear 1.25 2\nplum 2 0\n" as &[u8];
+        let total: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(4.5, total);
+    }
+
+    #[test]
+    fn test_multiplier_column_missing() {
+        let handler = InputHandler { ignore: 0, column: Some(1), multiplier_column: Some(2), ..handler(false) };
+        assert_eq!(Err("Missing column 2 at line 1".to_string()), handler.handle(0, "3"));
+        assert_eq!(Err("Invalid multiplier x at line 1".to_string()), handler.handle(0, "3 x"));
+        let silent = InputHandler { silent: true, ..handler };
+        assert_eq!(Ok(Some(1.5)), silent.handle(0, "3"));
+    }
+
+    #[test]
+    fn test_line_offset() {
+        let handler = InputHandler { ignore: 0, line_offset: 101, ..handler(false) };
+        assert_eq!(Err("Failed to parse x at line 101".to_string()), handler.handle(0, "x"));
+        assert_eq!(Err("Failed to parse y at line 104".to_string()), handler.handle(3, "y"));
+        let handler = InputHandler { column: Some(2), ..handler };
+        assert_eq!(Err("Missing column 2 at line 102".to_string()), handler.handle(1, "1"));
+        let lines = [(0, "1".to_string()), (1, "x".to_string())];
+        assert_eq!(vec!["   99 | 1", "> 100 | x"], context_lines(lines.iter(), 1, 99));
+    }
+
+    #[test]
+    fn test_bool_as_number() {
+        let handler = InputHandler { ignore: 0, identity: 0., bool_as_number: true, ..handler(false) };
+        let input = b"true\nNo\n2.5\nYES\nfalse\n" as &[u8];
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(4.5, sum);
+        assert_eq!(Err("Failed to parse maybe at line 1".to_string()), handler.handle(0, "maybe"));
+        let strict = InputHandler { bool_as_number: false, ..handler };
+        assert!(strict.handle(0, "true").is_err());
+    }
+
+    #[test]
+    fn test_log_precision() {
+        let handler = InputHandler { bits: FloatBits::Double, ..handler(false) };
+        assert_eq!("0.3333333333333333", handler.logged(1. / 3.));
+        let concise = InputHandler { log_precision: Some(3), ..handler };
+        assert_eq!("0.333", concise.logged(1. / 3.));
+        assert_eq!("1.500", concise.logged(1.5));
+        assert_eq!("0", InputHandler { log_precision: Some(0), ..handler }.logged(0.25));
+        assert_eq!("0.33333334", InputHandler { bits: FloatBits::Single, ..handler }.logged(1. / 3.));
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(Ok("12.5".to_string()), decode_base64("MTIuNQ=="));
+        assert!(decode_base64("not base64!").is_err());
+        assert!(decode_base64("/w==").is_err());
+        let handler = InputHandler { ignore: 1, identity: 0., base64: true, ..handler(false) };
+        // a header, 12.5, -2 and 1e3 with a trailing newline
+        let input = b"value\nMTIuNQ==\nLTI=\r\nMWUzCg==\n" as &[u8];
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(1010.5, sum);
+        let invalid = b"value\nMTIuNQ==\n7\nLTI=\n" as &[u8];
+        assert_eq!(12.5, handler.parse_input(handler.clean_and_enumerate(invalid)).sum::<f64>());
+        let silent = InputHandler { silent: true, ..handler };
+        assert_eq!(17.5, silent.parse_input(silent.clean_and_enumerate(invalid)).sum::<f64>());
+    }
+
+    #[test]
+    fn test_leading_plus() {
+        let base = InputHandler { ignore: 0, identity: 0., ..handler(false) };
+        let modes = [
+            ("float", base),
+            ("double", InputHandler { bits: FloatBits::Double, ..base }),
+            ("duration", InputHandler { duration: true, ..base }),
+            ("bool as number", InputHandler { bool_as_number: true, ..base }),
+            ("no scientific", InputHandler { no_scientific: true, ..base }),
+            ("column", InputHandler { column: Some(2), ..base }),
+            ("csv", InputHandler { column: Some(2), csv: true, ..base }),
+        ];
+        for (mode, handler) in modes {
+            let line = match (handler.column, handler.csv) {
+                (Some(_), true) => "x,+42",
+                (Some(_), false) => "x +42",
+                (None, _) => "+42"
+            };
+            assert_eq!(Ok(Some(42.)), handler.handle(0, line), "{}", mode);
+        }
+        let grouped = InputHandler { space_grouped: true, ..base };
+        assert_eq!(Some(1042.), grouped.parse_input(grouped.clean_and_enumerate(b"+1 042\n" as &[u8])).next());
+        let csv = InputHandler { column: Some(1), csv: true, ..base };
+        assert_eq!(Ok(Some(1042.)), csv.handle(0, "\"+1,042\""));
+        assert_eq!(Ok(Some(90.)), InputHandler { duration: true, ..base }.handle(0, "+1m30s"));
+        assert_eq!(Some(42.), super::timestamp::seconds("+42"));
+    }
+
+    #[test]
+    fn test_no_scientific_input() {
+        let handler = InputHandler { ignore: 0, identity: 0., no_scientific: true, ..handler(false) };
+        assert_eq!(Ok(Some(1000.)), handler.handle(0, "1000"));
+        assert_eq!(Ok(Some(-2.5)), handler.handle(0, "-2.5"));
+        assert_eq!(Err("Failed to parse 1e3 at line 1".to_string()), handler.handle(0, "1e3"));
+        assert!(handler.handle(0, "2.5E-1").is_err());
+        let silent = InputHandler { silent: true, ..handler };
+        let sum: f64 = silent.parse_input(silent.clean_and_enumerate(b"1000\n1e3\n1\n" as &[u8])).sum();
+        assert_eq!(1001., sum);
+        let booleans = InputHandler { bool_as_number: true, ..handler };
+        assert_eq!(Ok(Some(1.)), booleans.handle(0, "yes"));
+        let lenient = InputHandler { no_scientific: false, ..handler };
+        assert_eq!(Ok(Some(1000.)), lenient.handle(0, "1e3"));
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let handler = InputHandler { ignore: 0, identity: 0., bool_as_number: true, duration: true, ..handler(false) };
+        let input = b"True\nFALSE\nyes\n1M30S\n500Ms\n" as &[u8];
+        assert!(handler.parse_input(handler.clean_and_enumerate(input)).nth(3).is_none());
+        let ignoring = InputHandler { ignore_case: true, ..handler };
+        let sum: f64 = ignoring.parse_input(ignoring.clean_and_enumerate(input)).sum();
+        assert_eq!(92.5, sum);
+        assert_eq!(Ok(Some(1.5)), ignoring.handle(0, "1.5E0"));
+        assert!(Opts::try_parse_from(["mathcli", "--ignore-case", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(Ok(()), compare(12., Some(10.), None, 0., FloatBits::Single));
+        assert_eq!(Err("8 is not greater than 10".to_string()), compare(8., Some(10.), None, 0., FloatBits::Single));
+        assert_eq!(Err("10 is not greater than 10".to_string()), compare(10., Some(10.), None, 0., FloatBits::Single));
+        assert_eq!(Ok(()), compare(-1., None, Some(0.), 0., FloatBits::Single));
+        assert_eq!(Err("5 is not less than 0".to_string()), compare(5., None, Some(0.), 0., FloatBits::Single));
+        assert_eq!(Ok(()), compare(5., Some(0.), Some(10.), 0., FloatBits::Single));
+        assert!(compare(f64::NAN, Some(0.), Some(10.), 0., FloatBits::Single).is_err());
+    }
+
+    #[test]
+    fn test_compare_epsilon() {
+        // 0.1 + 0.2 in f32 comes out a little above 0.3
+        let sum = (0.1f32 + 0.2f32) as f64;
+        assert!(compare(sum, None, Some(0.3), 0., FloatBits::Single).is_err());
+        assert_eq!(Ok(()), compare(sum, None, Some(0.3), 1e-6, FloatBits::Single));
+        assert_eq!(Ok(()), compare(10., Some(10.), None, 0.001, FloatBits::Single));
+        assert_eq!(Ok(()), compare(9.9995, Some(10.), None, 0.001, FloatBits::Double));
+        assert!(compare(9.998, Some(10.), None, 0.001, FloatBits::Double).is_err());
+        assert!(compare(10.5, None, Some(10.), 0.25, FloatBits::Double).is_err());
+        assert!(compare(f64::NAN, Some(0.), None, 1., FloatBits::Single).is_err());
+    }
+
+    #[test]
+    fn test_template_from_cli() {
+        let opts = Opts::try_parse_from(["mathcli", "--template", "Total: {result} over {count} items, {min}-{max} by {operation}", "sub"]).unwrap();
+        let summary = [4., -2., 7.].iter().fold((0, None), |summary, &val| summarize(summary, val));
+        assert_eq!((3, Some((-2., 7.))), summary);
+        let (count, extent) = summary;
+        let (min, max) = extent.unwrap();
+        let formatter = OutputFormatter::new(&opts);
+        let values = [("result", formatter.format(-1.)), ("count", count.to_string()), ("operation", opts.subcmd.as_ref().unwrap().name().to_string()),
+            ("min", formatter.format(min)), ("max", formatter.format(max))];
+        assert_eq!("Total: -1 over 3 items, -2-7 by sub", opts.template.unwrap().fill(&values));
+        assert!(Opts::try_parse_from(["mathcli", "--template", "{sum}", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(Ok(0), exit_code(0.));
+        assert_eq!(Ok(1), exit_code(1.));
+        assert_eq!(Ok(42), exit_code(42.9));
+        assert_eq!(Ok(255), exit_code(255.));
+        assert_eq!(Ok(0), exit_code(256.));
+        assert_eq!(Ok(44), exit_code(300.));
+        assert_eq!(Ok(255), exit_code(-1.));
+        assert_eq!(Ok(0), exit_code(-0.5));
+        assert_eq!(Ok(0), exit_code(1e300));
+        assert!(exit_code(f64::NAN).is_err());
+        assert!(exit_code(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_nonzero() {
+        assert_eq!(Ok(()), nonzero(0.5, 0., FloatBits::Single));
+        assert_eq!(Ok(()), nonzero(-3., 0., FloatBits::Single));
+        assert_eq!(Err("The result 0 is zero".to_string()), nonzero(0., 0., FloatBits::Single));
+        assert!(nonzero(-0., 0., FloatBits::Single).is_err());
+        assert!(nonzero(1e-9, 1e-6, FloatBits::Double).is_err());
+        assert_eq!(Ok(()), nonzero(1e-3, 1e-6, FloatBits::Double));
+        // NaN isn't zero, whatever else is wrong with it
+        assert_eq!(Ok(()), nonzero(f64::NAN, 1., FloatBits::Double));
+        assert!(Opts::try_parse_from(["mathcli", "--assert-nonzero", "--compare-gt", "1", "add"]).unwrap().assert_nonzero);
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!("12.5", strip_ansi("\x1b[1;31m12.5\x1b[0m"));
+        assert_eq!("3 ms", strip_ansi("\x1b[32m3\x1b[m \x1b(Bms"));
+        assert_eq!("plain", strip_ansi("plain"));
+        assert_eq!("", strip_ansi("\x1b[31"));
+    }
+
+    #[test]
+    fn test_strip_ansi_lines() {
+        let handler = InputHandler { ignore: 0, identity: 0., strip_ansi: true, ..handler(false) };
+        let input = b"\x1b[32m1\x1b[0m\n  \x1b[1;33m2.5\x1b[0m  \n\x1b[0m\n4\n" as &[u8];
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        // the line that was only a reset is empty and ends the input
+        assert_eq!(3.5, sum);
+    }
+
+    #[test]
+    fn test_on_error_value() {
+        let handler = InputHandler { ignore: 0, identity: 0., on_error_value: Some(-1.), ..handler(false) };
+        let input = b"1\nn/a\n2\n?\n4\n" as &[u8];
+        let values: Vec<f64> = handler.parse_input(handler.clean_and_enumerate(input)).collect();
+        assert_eq!(vec![1., -1., 2., -1., 4.], values);
+        let handler = InputHandler { column: Some(2), ..handler };
+        assert_eq!(Ok(Some(-1.)), handler.handle(0, "only"));
+    }
+
+    #[test]
+    fn test_skip_zeros() {
+        let skipped = Cell::new(0);
+        let product = skip_zeros(vec![2., 0., 3., -0., 0.5].into_iter(), &skipped).reduce(std::ops::Mul::mul);
+        assert_eq!(Some(3.), product);
+        assert_eq!(2, skipped.get());
+        let opts = resolve(&["mathcli", "mul", "--skip-zeros"], None).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::Mul { skip_zeros: true })));
+    }
+
+    #[test]
+    fn test_per_file() {
+        let dir = std::env::temp_dir().join("mathcli_test_per_file");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a"), "1\n2\n3\n").unwrap();
+        std::fs::write(dir.join("b"), "4\n5\n").unwrap();
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let inputs = [Input::File(dir.join("a")), Input::File(dir.join("b"))];
+        let sources = inputs.iter().map(|input| handler.parse_input(handler.clean_and_enumerate(open(input))));
+        let sums = fold_each(sources, SubCommand::Add.operator(FloatBits::Single), None);
+        assert_eq!(vec![(6., 3), (9., 2)], sums);
+        let mul = Opts::try_parse_from(["mathcli", "--per-file", "--merge-op", "mul", "add"]).unwrap().merge_op.unwrap();
+        assert_eq!(Some(54.), sums.into_iter().map(|(sum, _)| sum).reduce(mul.operator(FloatBits::Single)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delimiter() {
+        assert_eq!(Ok('\t'), delimiter("tab"));
+        assert_eq!(Ok(','), delimiter("comma"));
+        assert_eq!(Ok(' '), delimiter("space"));
+        assert_eq!(Ok(';'), delimiter(";"));
+        assert!(delimiter("::").is_err());
+        assert!(delimiter("").is_err());
+    }
+
+    #[test]
+    fn test_running_window_stats_from_cli() {
+        let opts = Opts::try_parse_from(["mathcli", "--running-window-stats", "3", "--window-stat", "max", "add"]).unwrap();
+        assert_eq!(Some(3), opts.running_window_stats);
+        assert_eq!(super::window::Stat::Max, opts.window_stat);
+        assert!(Opts::try_parse_from(["mathcli", "--running-window-stats", "0", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_running_window_stats_skip_ignored() {
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let line = Cell::new(0);
+        let lines = handler.clean_and_enumerate(b"hdr\n1\n3\n" as &[u8]).inspect(|(i, _)| line.set(*i));
+        let values = handler.parse_input(lines).filter(|_| line.get() >= handler.ignore);
+        let stats: Vec<f64> = super::window::rolling(values, 2, super::window::Stat::Mean).collect();
+        assert_eq!(vec![1., 2.], stats);
+    }
+
+    #[test]
+    fn test_cache_args_leave_out_output_options() {
+        let args = |args: &[&str]| cache_args(&resolve(args, None).unwrap(), None);
+        let plain = args(&["mathcli", "add"]);
+        assert_eq!(plain, args(&["mathcli", "-v", "--history-file", "h", "--format-thousands", "--cache-dir", "c", "add"]));
+        assert_ne!(plain, args(&["mathcli", "--negate", "add"]));
+        assert_ne!(plain, args(&["mathcli", "-i", "1", "add"]));
+        assert_ne!(plain, args(&["mathcli", "sub"]));
+        assert_ne!(cache_args(&resolve(&["mathcli", "add"], None).unwrap(), Some(2.)), plain);
+    }
+
+    #[test]
+    fn test_no_cache_for_unseeded_sample() {
+        let dir = |args: &[&str]| cache_dir(&Opts::try_parse_from(args).unwrap()).cloned();
+        assert_eq!(Some("c".into()), dir(&["mathcli", "--cache-dir", "c", "add"]));
+        assert_eq!(Some("c".into()), dir(&["mathcli", "--cache-dir", "c", "--sample-rate", "0.5", "--sample-seed", "7", "add"]));
+        assert_eq!(None, dir(&["mathcli", "--cache-dir", "c", "--sample-rate", "0.5", "add"]));
+        assert_eq!(None, dir(&["mathcli", "add"]));
+        assert_eq!(None, dir(&["mathcli", "--cache-dir", "c", "--checkpoint-file", "p", "--resume", "add"]));
+    }
+
+    #[test]
+    fn test_exec_input() {
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let inputs = [Input::Command("printf '1\\n2\\n3.5\\n'".to_string())];
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(reader(&inputs))).sum();
+        assert_eq!(6.5, sum);
+    }
+
+    #[test]
+    fn test_ignore_overshoots_data() {
+        // a one line header, but --ignore 3 also swallows the value and the blank line after it
+        let handler = InputHandler { ignore: 3, identity: 0., ..handler(false) };
+        let input = b"total\n5\n\n7\n" as &[u8];
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(7., sum);
+        let strict = InputHandler { strict: true, ..handler };
+        assert_eq!(Err("Empty line 3 is among the 3 ignored lines, --ignore may be skipping data".to_string()), strict.handle(2, ""));
+        let values: Vec<f64> = strict.parse_input(strict.clean_and_enumerate(input)).collect();
+        assert_eq!(vec![0., 0.], values);
+        assert_eq!(Ok(Some(0.)), strict.handle(0, "total"));
+    }
+
+    #[test]
+    fn test_trimmed_mean_from_cli() {
+        let opts = resolve(&["mathcli", "trimmed-mean", "10"], None).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::TrimmedMean { percent }) if percent == 10.));
+        assert!(Opts::try_parse_from(["mathcli", "trimmed-mean", "50"]).is_err());
+        assert!(Opts::try_parse_from(["mathcli", "trimmed-mean", "--", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_file_subtotals() {
+        let dir = std::env::temp_dir().join("mathcli_test_file_subtotals");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a"), "1\n2\n3\n").unwrap();
+        std::fs::write(dir.join("b"), "4\n5\n").unwrap();
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let inputs = [Input::File(dir.join("a")), Input::File(dir.join("b"))];
+        let current = Rc::new(Cell::new(0));
+        let values = handler.parse_input(handler.clean_and_enumerate(marked_reader(&inputs, &current)));
+        let mut subtotals = Vec::new();
+        let result = fold_with_subtotals(values, std::ops::Add::add, None, &current, |i, subtotal| subtotals.push((inputs[i].to_string(), subtotal)));
+        assert_eq!(Some(15.), result);
+        assert_eq!(vec![(dir.join("a").display().to_string(), 6.), (dir.join("b").display().to_string(), 15.)], subtotals);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_join_digit_groups() {
+        assert_eq!("1234567", join_digit_groups("1 234 567"));
+        assert_eq!("1234.56", join_digit_groups("1 234.56"));
+        assert_eq!("-12345", join_digit_groups("-12\u{a0}345"));
+        assert_eq!("total 1234", join_digit_groups("total 1 234"));
+        for unchanged in &["12 34", "1 2345", "a 123", "1  234", "1 234567"] {
+            assert_eq!(*unchanged, join_digit_groups(unchanged));
+        }
+    }
+
+    #[test]
+    fn test_space_grouped_columns() {
+        let handler = InputHandler { ignore: 0, identity: 0., space_grouped: true, column: Some(2), ..handler(false) };
+        let input = b"a 1 234 567\nb 2 000\n" as &[u8];
+        let sum: f64 = handler.parse_input(handler.clean_and_enumerate(input)).sum();
+        assert_eq!(1236567., sum);
+    }
+
+    #[test]
+    fn test_input_locale() {
+        let french = InputHandler { ignore: 0, identity: 0., bits: FloatBits::Double, locale: Some("fr".parse().unwrap()), ..handler(false) };
+        assert_eq!(Ok(1234.56), french.parse("1 234,56"));
+        let columns = InputHandler { space_grouped: true, column: Some(2), ..french };
+        let sum: f64 = columns.parse_input(columns.clean_and_enumerate(b"a 1 234,56\nb 0,44\n" as &[u8])).sum();
+        assert_eq!(1235., sum);
+        let german = InputHandler { locale: Some("de".parse().unwrap()), ..french };
+        assert_eq!(Ok(1234.5), german.parse("1.234,5"));
+        assert_eq!(Some(1), german.decimals(0, "1.234,5"));
+    }
+
+    #[test]
+    fn test_count_distinct_from_cli() {
+        let opts = resolve(&["mathcli", "count-distinct", "--epsilon", "0.01"], None).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::CountDistinct { epsilon }) if epsilon == 0.01));
+    }
+
+    #[test]
+    fn test_weighted_by_line_count() {
+        let dir = std::env::temp_dir().join("mathcli_test_weighted_by_line_count");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a"), "1\n2\n3\n").unwrap();
+        std::fs::write(dir.join("b"), "10\n").unwrap();
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let inputs = [Input::File(dir.join("a")), Input::File(dir.join("b"))];
+        let sources = inputs.iter().map(|input| handler.parse_input(handler.clean_and_enumerate(open(input))));
+        let sums = fold_each(sources, SubCommand::Add.operator(FloatBits::Single), None);
+        assert_eq!(vec![(6., 3), (10., 1)], sums);
+        let means: Vec<(f64, usize)> = sums.into_iter().map(|(sum, count)| (sum / count as f64, count)).collect();
+        // (2 * 3 + 10 * 1) / 4, rather than the plain mean of the means, 6
+        assert_eq!(Some(4.), weighted_mean(&means));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(None, weighted_mean(&[]));
+        assert!(Opts::try_parse_from(["mathcli", "--weighted-by-line-count", "add"]).is_err());
+        assert!(Opts::try_parse_from(["mathcli", "--per-file", "--weighted-by-line-count", "--merge-op", "mul", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_json_array() {
+        let elements = super::json::elements("[1, 2, 3.5]").unwrap();
+        let loud = InputHandler { ignore: 0, ..handler(false) };
+        let values: Vec<f64> = loud.parse_input(elements.into_iter().enumerate()).collect();
+        assert_eq!(vec![1., 2., 3.5], values);
+        // non-numeric elements are bad values like any other, here standing in for the identity
+        let elements = super::json::elements("[1, null, 2]").unwrap();
+        let silent = InputHandler { ignore: 0, ..handler(true) };
+        let values: Vec<f64> = silent.parse_input(elements.into_iter().enumerate()).collect();
+        assert_eq!(vec![1., 1.5, 2.], values);
+        let opts = Opts::try_parse_from(["mathcli", "--json-array", "add"]).unwrap();
+        assert!(opts.json_array);
+        assert!(Opts::try_parse_from(["mathcli", "--json-array", "--per-file", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_rate() {
+        let opts = Opts::try_parse_from(["mathcli", "rate", "--duration", "4"]).unwrap();
+        let subcmd = opts.subcmd.unwrap();
+        assert!(matches!(subcmd, SubCommand::Rate { duration: Some(duration) } if duration == 4.));
+        // 1000 bytes in 4 seconds
+        let total = fold(vec![100., 400., 500.].into_iter(), subcmd.operator(FloatBits::Single), None);
+        assert_eq!(Ok(250.), rate(total.unwrap(), Some(4.), None));
+        assert!(Opts::try_parse_from(["mathcli", "rate", "--duration", "0"]).is_err());
+        assert!(Opts::try_parse_from(["mathcli", "rate", "--duration", "-1"]).is_err());
+        assert!(rate(1000., None, None).is_err());
+    }
+
+    #[test]
+    fn test_rate_from_timestamps() {
+        let input = b"time bytes\n2024-03-01T12:00:00Z 300\n2024-03-01T12:00:30Z 200\n1709294460 100\n" as &[u8];
+        let handler = InputHandler { ignore: 1, identity: 0., column: Some(2), ..handler(false) };
+        let span = super::timestamp::Span::default();
+        let total = handler.parse_input(handler.track_span(handler.clean_and_enumerate(input), 1, &span)).sum();
+        // 600 bytes over the minute from the first timestamp to the last
+        assert_eq!(Some(60.), span.seconds());
+        assert_eq!(Ok(10.), rate(total, None, span.seconds()));
+        assert_eq!(Ok(300.), rate(total, Some(2.), span.seconds()));
+        assert!(rate(total, None, Some(0.)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_timestamps() {
+        let input = b"100 1\nnoon 2\n160 3\n" as &[u8];
+        let handler = InputHandler { ignore: 0, identity: 0., column: Some(2), ..handler(false) };
+        let span = super::timestamp::Span::default();
+        let total: f64 = handler.parse_input(handler.track_span(handler.clean_and_enumerate(input), 1, &span)).sum();
+        assert_eq!((1., Some(0.)), (total, span.seconds()));
+        let silent = InputHandler { silent: true, ..handler };
+        let span = super::timestamp::Span::default();
+        let total: f64 = silent.parse_input(silent.track_span(silent.clean_and_enumerate(input), 1, &span)).sum();
+        assert_eq!((4., Some(60.)), (total, span.seconds()));
+    }
+
+    #[test]
+    fn test_first_and_last() {
+        let handler = handler(false);
+        let first = SubCommand::First.operator(FloatBits::Single);
+        let last = SubCommand::Last.operator(FloatBits::Single);
+        let values = || handler.parse_input(handler.clean_and_enumerate(&b"9\n9\n3\n1.5\n8\n\n7\n"[..])).skip(handler.ignore);
+        assert_eq!(Some(3.), fold(values(), first, None));
+        // stops at the empty line like any other operation
+        assert_eq!(Some(8.), fold(values(), last, None));
+        assert_eq!(None, fold(std::iter::empty(), first, None));
+        let opts = Opts::try_parse_from(["mathcli", "last"]).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::Last)));
+    }
+
+    #[test]
+    fn test_ignore_blank_only_lines_anywhere() {
+        let input = b"1\n   \n\t\n2\r\n\r\n3\n";
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        // by default the whitespace-only line is empty once trimmed, and stops the input
+        let sum = handler.parse_input(handler.clean_and_enumerate(&input[..])).reduce(SubCommand::Add.operator(FloatBits::Single));
+        assert_eq!(Some(1.), sum);
+        let skipping = InputHandler { skip_blank: true, ..handler };
+        let lines: Vec<(usize, String)> = skipping.clean_and_enumerate(&input[..]).collect();
+        assert_eq!(vec![(0, "1".to_string()), (3, "2".to_string()), (4, "".to_string()), (5, "3".to_string())], lines);
+        // the empty \r\n line still stops it
+        let sum = skipping.parse_input(skipping.clean_and_enumerate(&input[..])).reduce(SubCommand::Add.operator(FloatBits::Single));
+        assert_eq!(Some(3.), sum);
+    }
+
+    #[test]
+    fn test_checksum_from_cli() {
+        let opts = Opts::try_parse_from(["mathcli", "checksum"]).unwrap();
+        assert!(matches!(opts.subcmd, Some(SubCommand::Checksum)));
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let checksum = |input: &[u8]| super::transform::checksum(handler.parse_input(handler.clean_and_enumerate(input)));
+        // the same values written differently are the same stream
+        assert_eq!(checksum(b"1\n2.50\n"), checksum(b"1.0\n 2.5\n"));
+        assert_ne!(checksum(b"1\n2.5\n"), checksum(b"1\n2.6\n"));
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let opts = Opts::try_parse_from(["mathcli", "--sample-rate", "0.5", "--sample-seed", "42", "add"]).unwrap();
+        assert_eq!((Some(0.5), Some(42)), (opts.sample_rate, opts.sample_seed));
+        let handler = InputHandler { ignore: 0, ..handler(false) };
+        let kept = || -> Vec<f64> {
+            let values = handler.parse_input(handler.clean_and_enumerate(&b"1\n2\n3\n4\n5\n6\n7\n8\n"[..]));
+            super::sample::sample(values, 0.5, super::sample::Rng::new(42)).collect()
+        };
+        assert_eq!(kept(), kept());
+        assert!(Opts::try_parse_from(["mathcli", "--sample-rate", "1.5", "add"]).is_err());
+        assert!(Opts::try_parse_from(["mathcli", "--sample-seed", "1", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_echo_stderr() {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        write_result("1,234.5", LineEnding::Lf, &mut out, true, &mut err).unwrap();
+        assert_eq!(b"1,234.5\n", &out[..]);
+        assert_eq!(b"1,234.5\n", &err[..]);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        write_result("7", LineEnding::Lf, &mut out, false, &mut err).unwrap();
+        assert_eq!(b"7\n", &out[..]);
+        assert!(err.is_empty());
+        assert!(Opts::try_parse_from(["mathcli", "--echo-stderr", "--quiet-result", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_crlf_line_ending() {
+        let opts = Opts::try_parse_from(["mathcli", "--line-ending", "crlf", "--percent-of-total", "add"]).unwrap();
+        assert_eq!(LineEnding::Crlf, opts.line_ending);
+        let formatter = OutputFormatter::new(&opts);
+        let mut out = Vec::new();
+        let values = [1., 3.];
+        for (value, percent) in values.iter().zip(super::transform::percent_of_total(&values).unwrap()) {
+            write!(out, "{}{}", formatter.format_transformed(*value, percent, "%"), opts.line_ending).unwrap();
+        }
+        write_result("4", opts.line_ending, &mut out, false, &mut Vec::new()).unwrap();
+        assert_eq!("25%\r\n75%\r\n4\r\n", String::from_utf8(out).unwrap());
+        assert_eq!(LineEnding::Lf, Opts::try_parse_from(["mathcli", "add"]).unwrap().line_ending);
+        assert!(Opts::try_parse_from(["mathcli", "--line-ending", "cr", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_percent_of_total_skips_ignored() {
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let line = Cell::new(0);
+        let lines = handler.clean_and_enumerate(b"hdr\n1\n3\n\n" as &[u8]).inspect(|(i, _)| line.set(*i));
+        let values: Vec<f64> = handler.parse_input(lines).filter(|_| line.get() >= handler.ignore).collect();
+        assert_eq!(vec![1., 3.], values);
+        assert_eq!(vec![25., 75.], super::transform::percent_of_total(&values).unwrap());
+    }
+
+    #[test]
+    fn test_list_operations() {
+        let operations = super::operations();
+        let add = operations.iter().find(|(name, _, _)| name == "add").unwrap();
+        assert_eq!((&Some("0.0".to_string()), "Add all inputs."), (&add.1, add.2.as_str()));
+        let names: Vec<&str> = operations.iter().map(|(name, _, _)| name.as_str()).collect();
+        for known in ["sub", "mul", "div", "trimmed-mean", "first", "rate"] {
+            assert!(names.contains(&known), "{} is missing", known);
+        }
+        assert!(!names.contains(&"list-operations"));
+    }
+
+    #[test]
+    fn test_csv_quoted_fields() {
+        assert_eq!(Some("1234".to_string()), csv_field(r#""1,234",5"#, 1));
+        assert_eq!(Some("5".to_string()), csv_field(r#""1,234",5"#, 2));
+        assert_eq!(Some("a, b".to_string()), csv_field(r#"x,"a, b",3"#, 2));
+        assert_eq!(Some("say \"hi\"".to_string()), csv_field(r#""say ""hi""""#, 1));
+        assert_eq!(None, csv_field("1,2", 3));
+        assert_eq!(None, csv_field("1,2", 0));
+        let handler = InputHandler { ignore: 1, identity: 0., column: Some(2), csv: true, ..handler(false) };
+        let input = b"name,amount\n\"Smith, J\",\"1,234.5\"\nDoe, 10\n" as &[u8];
+        let sum = handler.parse_input(handler.clean_and_enumerate(input)).reduce(SubCommand::Add.operator(FloatBits::Single));
+        assert_eq!(Some(1244.5), sum);
+        assert!(Opts::try_parse_from(["mathcli", "--csv", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_transpose() {
+        let input = b"a,b,c\n1,2,3\n4,5,6\n7,8,9\n" as &[u8];
+        let handler = InputHandler { ignore: 1, identity: 0., csv: true, ..handler(false) };
+        let columns = handler.transpose(handler.clean_and_enumerate(input), None, None).unwrap();
+        assert_eq!(vec![vec![1., 4., 7.], vec![2., 5., 8.], vec![3., 6., 9.]], columns);
+        let sums: Vec<f64> = fold_each(columns.into_iter().map(Vec::into_iter), SubCommand::Add.operator(FloatBits::Single), None)
+            .into_iter().map(|(sum, _)| sum).collect();
+        assert_eq!(vec![12., 15., 18.], sums);
+        let whitespace = InputHandler { csv: false, ..handler };
+        let columns = whitespace.transpose(whitespace.clean_and_enumerate(b"x y z\n1 2 3\n4 5 6\n7 8 9\n\n10 11 12\n" as &[u8]), None, None);
+        assert_eq!(Ok(vec![vec![1., 4., 7.], vec![2., 5., 8.], vec![3., 6., 9.]]), columns);
+        assert!(Opts::try_parse_from(["mathcli", "--transpose", "--csv", "add"]).is_ok());
+        assert!(Opts::try_parse_from(["mathcli", "--transpose", "--column", "2", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_per_field() {
+        let input = b"a b c\n1 10 100\n2 20 200\n3 30 300\n\n4 40 400\n" as &[u8];
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let add = SubCommand::Add.operator(FloatBits::Single);
+        assert_eq!(Ok(vec![6., 60., 600.]), handler.fold_fields(handler.clean_and_enumerate(input), add, None));
+        let sub = SubCommand::Sub.operator(FloatBits::Single);
+        assert_eq!(Ok(vec![-4., -40., -400.]), handler.fold_fields(handler.clean_and_enumerate(input), sub, None));
+        assert_eq!(Ok(vec![-6., -60., -600.]), handler.fold_fields(handler.clean_and_enumerate(input), sub, Some(0.)));
+        let csv = InputHandler { csv: true, ..handler };
+        let input = b"a,b\n\"1,000\",2\n3,4\n" as &[u8];
+        assert_eq!(Ok(vec![1003., 6.]), csv.fold_fields(csv.clean_and_enumerate(input), add, None));
+        assert!(Opts::try_parse_from(["mathcli", "--per-field", "--csv", "add"]).is_ok());
+        assert!(Opts::try_parse_from(["mathcli", "--per-field", "--transpose", "add"]).is_err());
+    }
+
+    #[test]
+    fn test_per_field_ragged_lines() {
+        let input = b"1 2 3\n4 5\n6 7 8 9\n" as &[u8];
+        let handler = InputHandler { ignore: 0, identity: 0., ..handler(false) };
+        let add = SubCommand::Add.operator(FloatBits::Single);
+        assert_eq!(Err("Line 2 has 2 of the 3 columns".to_string()), handler.fold_fields(handler.clean_and_enumerate(input), add, None));
+        let silent = InputHandler { silent: true, ..handler };
+        assert_eq!(Ok(vec![11., 14., 11., 9.]), silent.fold_fields(silent.clean_and_enumerate(input), add, None));
+    }
+
+    #[test]
+    fn test_transpose_ragged_rows() {
+        let input = b"1 2 3\n4 5\n7 8 9\n" as &[u8];
+        let handler = InputHandler { ignore: 0, identity: 0., ..handler(false) };
+        assert_eq!(Err("Line 2 has 2 of the 3 columns".to_string()), handler.transpose(handler.clean_and_enumerate(input), None, None));
+        let padded = handler.transpose(handler.clean_and_enumerate(input), Some(0.), None).unwrap();
+        assert_eq!(vec![vec![1., 4., 7.], vec![2., 5., 8.], vec![3., 0., 9.]], padded);
+        assert!(handler.transpose(handler.clean_and_enumerate(b"1 2\n3 x\n" as &[u8]), None, None).is_err());
+    }
+
+    #[test]
+    fn test_token_counts() {
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let input = b"id a b\n1 2 3\n4  5\t6\n7 8\n\n9\n" as &[u8];
+        assert_eq!(vec![3, 3, 2], handler.token_counts(handler.clean_and_enumerate(input)).collect::<Vec<_>>());
+        let csv = InputHandler { csv: true, ..handler };
+        let input = b"id,a\n\"1,000\",2\n3,,4\n" as &[u8];
+        assert_eq!(vec![2, 3], csv.token_counts(csv.clean_and_enumerate(input)).collect::<Vec<_>>());
+        assert!(Opts::try_parse_from(["mathcli", "--count-tokens", "--csv", "add"]).is_ok());
+    }
+
+    #[test]
+    fn test_skip_empty_tokens() {
+        let csv = InputHandler { ignore: 0, csv: true, ..handler(false) };
+        let skipping = InputHandler { skip_empty_tokens: true, ..csv };
+        assert_eq!(3, csv.fields("1,,2").len());
+        let sum: f64 = skipping.fields("1,,2").iter().map(|field| field.parse::<f64>().unwrap()).sum();
+        assert_eq!(3., sum);
+        assert_eq!(Some("".into()), csv.field("1,,2", 2));
+        assert_eq!(Some("2".into()), skipping.field("1,,2", 2));
+        assert_eq!(Ok(vec![4., 6.]), skipping.fold_fields(skipping.clean_and_enumerate(b"1,,2\n3,4\n" as &[u8]), std::ops::Add::add, None));
+        assert!(Opts::try_parse_from(["mathcli", "--skip-empty-tokens", "-c", "1", "add"]).is_err());
+        assert!(Opts::try_parse_from(["mathcli", "--skip-empty-tokens", "--csv", "-c", "1", "add"]).is_ok());
+    }
+
+    #[test]
+    fn test_expect_tokens() {
+        let handler = InputHandler { ignore: 1, identity: 0., column: Some(2), ..handler(false) };
+        let consistent = b"id n\n1 10\n2 20\n" as &[u8];
+        let checked = handler.check_tokens(handler.clean_and_enumerate(consistent), 2);
+        assert_eq!(30., handler.parse_input(checked).sum::<f64>());
+        let ragged = b"id n\n1 10\n2 20 x\n3 30\n" as &[u8];
+        // only a warning without --strict
+        let checked = handler.check_tokens(handler.clean_and_enumerate(ragged), 2);
+        assert_eq!(60., handler.parse_input(checked).sum::<f64>());
+        let strict = InputHandler { strict: true, ..handler };
+        let checked = strict.check_tokens(strict.clean_and_enumerate(ragged), 2);
+        assert_eq!(10., strict.parse_input(checked).sum::<f64>());
+    }
+
+    #[test]
+    fn test_columns() {
+        let input = b"id a b c\n1 10 20 30\n2 1.5 2.5 3\n" as &[u8];
+        let handler = InputHandler { ignore: 1, identity: 0., ..handler(false) };
+        let fields: Vec<(usize, String)> = handler.split_columns(handler.clean_and_enumerate(input), (2, 3)).collect();
+        let expected = vec![(0, "id a b c"), (1, "10"), (1, "20"), (2, "1.5"), (2, "2.5")];
+        assert_eq!(expected.into_iter().map(|(i, f)| (i, f.to_string())).collect::<Vec<_>>(), fields);
+        let sum: f64 = handler.parse_input(handler.split_columns(handler.clean_and_enumerate(input), (2, 4))).sum();
+        assert_eq!(67., sum);
+    }
+
+    #[test]
+    fn test_columns_out_of_range() {
+        let input = b"1 2 3\n4 5\n6 7 8\n" as &[u8];
+        let handler = InputHandler { ignore: 0, identity: 0., ..handler(false) };
+        let sum: f64 = handler.parse_input(handler.split_columns(handler.clean_and_enumerate(input), (2, 3))).sum();
+        // stops at the short line
+        assert_eq!(5., sum);
+        let silent = InputHandler { silent: true, ..handler };
+        let sum: f64 = silent.parse_input(silent.split_columns(silent.clean_and_enumerate(input), (2, 3))).sum();
+        assert_eq!(5. + 5. + 15., sum);
+    }
+
+    #[test]
+    fn test_column_range() {
+        assert_eq!(Ok((2, 5)), column_range("2-5"));
+        assert_eq!(Ok((3, 3)), column_range("3"));
+        assert!(column_range("0-2").is_err());
+        assert!(column_range("5-2").is_err());
+        assert!(column_range("a-b").is_err());
+        assert!(Opts::try_parse_from(["mathcli", "--columns", "2-3", "-c", "1", "add"]).is_err());
+    }
 }
@@ -0,0 +1,101 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// The smallest pending value of one source, ordered so the `BinaryHeap` pops the minimum.
+struct HeapEntry {
+    value: f64,
+    source: usize,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, BinaryHeap is a max-heap. ties go to the earlier source
+        other.value.total_cmp(&self.value)
+            .then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+/// k-way merge of already sorted (ascending) streams into one sorted stream.
+/// Reports a source that turns out not to be sorted as an error, without stopping: the output is
+/// then only as sorted as the input.
+pub struct MergeSorted<I: Iterator<Item=f64>> {
+    sources: Vec<I>,
+    last: Vec<Option<f64>>,
+    heap: BinaryHeap<HeapEntry>,
+}
+
+impl<I: Iterator<Item=f64>> MergeSorted<I> {
+    pub fn new(sources: Vec<I>) -> Self {
+        let last = vec![None; sources.len()];
+        let mut merge = MergeSorted { sources, last, heap: BinaryHeap::new() };
+        for source in 0..merge.sources.len() {
+            merge.advance(source);
+        }
+        merge
+    }
+
+    /// Pull the next value of `source` onto the heap, checking it against the previous one.
+    fn advance(&mut self, source: usize) {
+        if let Some(value) = self.sources[source].next() {
+            if let Some(prev) = self.last[source] {
+                if value < prev {
+                    log::error!("Input {} is not sorted, {} follows {}", source + 1, value, prev);
+                }
+            }
+            self.last[source] = Some(value);
+            self.heap.push(HeapEntry { value, source });
+        }
+    }
+}
+
+impl<I: Iterator<Item=f64>> Iterator for MergeSorted<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let HeapEntry { value, source } = self.heap.pop()?;
+        self.advance(source);
+        Some(value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::MergeSorted;
+
+    #[test]
+    fn test_merge_three_sorted() {
+        let a = vec![1., 4., 7.];
+        let b = vec![2., 5., 8., 9.];
+        let c = vec![0., 3., 6.];
+        let merged: Vec<f64> = MergeSorted::new(vec![a.into_iter(), b.into_iter(), c.into_iter()]).collect();
+        assert_eq!(vec![0., 1., 2., 3., 4., 5., 6., 7., 8., 9.], merged);
+    }
+
+    #[test]
+    fn test_merge_with_empty_source() {
+        let merged: Vec<f64> = MergeSorted::new(vec![vec![1., 3.].into_iter(), vec![].into_iter(), vec![2.].into_iter()]).collect();
+        assert_eq!(vec![1., 2., 3.], merged);
+    }
+
+    #[test]
+    fn test_merge_unsorted_keeps_all_values() {
+        let merged: Vec<f64> = MergeSorted::new(vec![vec![3., 1.].into_iter(), vec![2.].into_iter()]).collect();
+        assert_eq!(vec![2., 3., 1.], merged);
+    }
+}
@@ -0,0 +1,106 @@
+use std::cell::Cell;
+use std::str::FromStr;
+
+/// The order --assert-monotonic expects the values in.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Order {
+    Increasing,
+    Decreasing,
+}
+
+impl Order {
+    /// Whether `val` may follow `prev`. Repeated values are allowed either way.
+    fn allows(self, prev: f64, val: f64) -> bool {
+        match self {
+            Order::Increasing => val >= prev,
+            Order::Decreasing => val <= prev
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "inc" => Ok(Order::Increasing),
+            "dec" => Ok(Order::Decreasing),
+            _ => Err(format!("Unknown order {}, options are inc, dec", s))
+        }
+    }
+}
+
+/// Pass `values` through until one breaks `order`, then stop and describe it in `broken`.
+/// `line` holds the index of the line the latest value was read from; values from lines before
+/// `ignore` stand in for the identity and aren't checked.
+pub fn check<'a>(
+    values: impl Iterator<Item=f64> + 'a,
+    order: Order,
+    line: &'a Cell<usize>,
+    ignore: usize,
+    broken: &'a Cell<Option<String>>,
+) -> impl Iterator<Item=f64> + 'a {
+    let mut prev = None;
+    values.map_while(move |val| {
+        if line.get() < ignore {
+            return Some(val)
+        }
+        match prev {
+            Some(p) if !order.allows(p, val) => {
+                broken.set(Some(format!("Line {} ({}) is out of order after {}", line.get() + 1, val, p)));
+                None
+            },
+            _ => {
+                prev = Some(val);
+                Some(val)
+            }
+        }
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Order, check};
+    use std::cell::Cell;
+
+    fn run(values: &[f64], order: Order, ignore: usize) -> (Vec<f64>, Option<String>) {
+        let line = Cell::new(0);
+        let broken = Cell::new(None);
+        let lines = values.iter().enumerate().map(|(i, v)| {
+            line.set(i);
+            *v
+        });
+        let passed = check(lines, order, &line, ignore, &broken).collect();
+        (passed, broken.take())
+    }
+
+    #[test]
+    fn test_monotonic_passes() {
+        assert_eq!((vec![1., 2., 2., 5.], None), run(&[1., 2., 2., 5.], Order::Increasing, 0));
+        assert_eq!((vec![5., 3., -1.], None), run(&[5., 3., -1.], Order::Decreasing, 0));
+    }
+
+    #[test]
+    fn test_monotonic_breaks() {
+        let (passed, broken) = run(&[1., 3., 2., 4.], Order::Increasing, 0);
+        assert_eq!(vec![1., 3.], passed);
+        assert_eq!(Some("Line 3 (2) is out of order after 3".to_string()), broken);
+        let (_, broken) = run(&[3., 4.], Order::Decreasing, 0);
+        assert_eq!(Some("Line 2 (4) is out of order after 3".to_string()), broken);
+    }
+
+    #[test]
+    fn test_monotonic_skips_ignored() {
+        // the ignored header isn't compared with the values after it
+        assert_eq!((vec![100., 1., 2.], None), run(&[100., 1., 2.], Order::Increasing, 1));
+    }
+
+    #[test]
+    fn test_order_from_str() {
+        assert_eq!(Ok(Order::Increasing), "inc".parse());
+        assert_eq!(Ok(Order::Decreasing), "dec".parse());
+        assert!("up".parse::<Order>().is_err());
+    }
+}
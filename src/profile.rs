@@ -0,0 +1,154 @@
+use std::cell::Cell;
+use std::io::{self, BufRead, Read};
+use std::time::Instant;
+
+/// Counters for --profile and --report-lines, reported to stderr once the run is done.
+pub struct Profile {
+    started: Instant,
+    lines: Cell<usize>,
+    values: Cell<usize>,
+    bytes: Cell<u64>,
+    buffered: Cell<usize>,
+    read: Cell<usize>,
+    next: Cell<usize>,
+    ignored: Cell<usize>,
+}
+
+impl Profile {
+    pub fn start() -> Self {
+        Profile {
+            started: Instant::now(),
+            lines: Cell::new(0),
+            values: Cell::new(0),
+            bytes: Cell::new(0),
+            buffered: Cell::new(0),
+            read: Cell::new(0),
+            next: Cell::new(0),
+            ignored: Cell::new(0),
+        }
+    }
+
+    pub fn count_line(&self) {
+        self.lines.set(self.lines.get() + 1);
+    }
+
+    pub fn count_value(&self) {
+        self.values.set(self.values.get() + 1);
+    }
+
+    /// Note that the line with index `i` came through, along with any skipped since the one
+    /// before it. An index lower than the last starts the lines of another input.
+    pub fn reach_line(&self, i: usize) {
+        let next = self.next.get();
+        let read = match i >= next {
+            true => i + 1 - next,
+            false => i + 1
+        };
+        self.read.set(self.read.get() + read);
+        self.next.set(i + 1);
+    }
+
+    /// Count a value that stood in for a line skipped by --ignore.
+    pub fn count_ignored(&self) {
+        self.ignored.set(self.ignored.get() + 1);
+    }
+
+    /// Note that `len` values are held in memory at once.
+    pub fn buffer(&self, len: usize) {
+        self.buffered.set(self.buffered.get().max(len));
+    }
+
+    /// Count the bytes taken from `inner`.
+    pub fn counted<R: BufRead>(&self, inner: R) -> Counted<'_, R> {
+        Counted { inner, bytes: &self.bytes }
+    }
+
+    /// One `name: value` line per counter.
+    pub fn report(&self) -> Vec<String> {
+        vec![
+            format!("lines read: {}", self.lines.get()),
+            format!("values parsed: {}", self.values.get()),
+            format!("bytes read: {}", self.bytes.get()),
+            format!("peak buffer: {} values", self.buffered.get()),
+            format!("wall time: {:?}", self.started.elapsed()),
+        ]
+    }
+
+    /// One `name: value` line each for the lines read, the ones --ignore skipped, the values
+    /// parsed from the rest, and the lines skipped without giving a value, like blank ones.
+    pub fn line_report(&self) -> Vec<String> {
+        let (read, ignored) = (self.read.get(), self.ignored.get());
+        let parsed = self.values.get().saturating_sub(ignored);
+        vec![
+            format!("lines read: {}", read),
+            format!("lines ignored: {}", ignored),
+            format!("values parsed: {}", parsed),
+            format!("lines skipped: {}", read.saturating_sub(ignored + parsed)),
+        ]
+    }
+}
+
+/// A reader adding up the bytes read from it in `bytes`.
+pub struct Counted<'a, R> {
+    inner: R,
+    bytes: &'a Cell<u64>,
+}
+
+impl<R: BufRead> Read for Counted<'_, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(out)?;
+        self.bytes.set(self.bytes.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.bytes.set(self.bytes.get() + amt as u64);
+        self.inner.consume(amt)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::Profile;
+    use std::io::BufRead;
+
+    #[test]
+    fn test_report_fields() {
+        let profile = Profile::start();
+        let lines = profile.counted(&b"1\n22\n"[..]).lines().count();
+        (0..lines).for_each(|_| profile.count_line());
+        profile.count_value();
+        profile.buffer(3);
+        profile.buffer(1);
+        let report = profile.report();
+        assert_eq!(vec!["lines read: 2", "values parsed: 1", "bytes read: 5", "peak buffer: 3 values"], report[..4]);
+        assert!(report[4].starts_with("wall time: "));
+    }
+
+    #[test]
+    fn test_line_report() {
+        let profile = Profile::start();
+        // a header, then values with two blank lines skipped between them, then the empty line
+        // the input stopped at
+        for i in [0, 1, 2, 5, 6] {
+            profile.reach_line(i);
+        }
+        profile.count_ignored();
+        (0..4).for_each(|_| profile.count_value());
+        // a second input
+        for i in [0, 1] {
+            profile.reach_line(i);
+        }
+        profile.count_value();
+        let report = profile.line_report();
+        assert_eq!(vec!["lines read: 9", "lines ignored: 1", "values parsed: 4", "lines skipped: 4"], report);
+    }
+}
@@ -0,0 +1,59 @@
+/// The SplitMix64 generator: small, fast and the same on every platform, which is all
+/// --sample-rate needs from its random numbers.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in [0, 1), from the top 53 bits so every value is exactly representable.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Keep each of `values` with probability `rate`, drawing from `rng`.
+pub fn sample(values: impl Iterator<Item=f64>, rate: f64, mut rng: Rng) -> impl Iterator<Item=f64> {
+    values.filter(move |_| rng.next_f64() < rate)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Rng, sample};
+
+    #[test]
+    fn test_fixed_seed_is_deterministic() {
+        let values = || (1..=20).map(f64::from);
+        let first: Vec<f64> = sample(values(), 0.5, Rng::new(42)).collect();
+        let second: Vec<f64> = sample(values(), 0.5, Rng::new(42)).collect();
+        assert_eq!(first, second);
+        assert!(!first.is_empty() && first.len() < 20);
+        let other: Vec<f64> = sample(values(), 0.5, Rng::new(7)).collect();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_rate_bounds() {
+        assert_eq!(0, sample((0..100).map(f64::from), 0., Rng::new(1)).count());
+        assert_eq!(100, sample((0..100).map(f64::from), 1., Rng::new(1)).count());
+    }
+
+    #[test]
+    fn test_rate_is_roughly_kept() {
+        let kept = sample((0..10000).map(f64::from), 0.25, Rng::new(3)).count();
+        assert!((2250..2750).contains(&kept), "kept {}", kept);
+    }
+}
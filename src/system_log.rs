@@ -0,0 +1,61 @@
+use syslog::{BasicLogger, Facility, Formatter3164, Logger, LoggerBackend};
+
+/// Parse the facility for --syslog-facility, like user or local0.
+pub fn facility(s: &str) -> Result<Facility, String> {
+    s.parse().map_err(|()| format!("Unknown syslog facility {}, e.g. user, daemon or local0 to local7", s))
+}
+
+/// How messages from this run are written for syslog: tagged with `tag` and this process's id.
+pub fn formatter(facility: Facility, tag: &str) -> Formatter3164 {
+    Formatter3164 { facility, hostname: None, process: tag.to_string(), pid: std::process::id() }
+}
+
+/// Connect to the local syslog daemon.
+pub fn connect(facility: Facility, tag: &str) -> Result<Logger<LoggerBackend, Formatter3164>, String> {
+    syslog::unix(formatter(facility, tag)).map_err(|e| format!("Failed to connect to syslog: {}", e))
+}
+
+/// Send log records to syslog instead of stderr, as many of them as `verbosity` would show there.
+pub fn init(facility: Facility, tag: &str, verbosity: usize) -> Result<(), String> {
+    let logger = connect(facility, tag)?;
+    let level = match verbosity {
+        0 => log::LevelFilter::Error,
+        1 => log::LevelFilter::Warn,
+        2 => log::LevelFilter::Info,
+        3 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace
+    };
+    log::set_boxed_logger(Box::new(BasicLogger::new(logger)))
+        .map(|()| log::set_max_level(level))
+        .map_err(|e| e.to_string())
+}
+
+/// The message the result is sent to syslog as.
+pub fn result_message(formatted: &str) -> String {
+    format!("result {}", formatted)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{facility, formatter, result_message};
+    use syslog::{Facility, LogFormat, Severity};
+
+    #[test]
+    fn test_result_message() {
+        let mut message = Vec::new();
+        formatter(Facility::LOG_LOCAL0, "totals").format(&mut message, Severity::LOG_INFO, result_message("1,234.5")).unwrap();
+        let message = String::from_utf8(message).unwrap();
+        // local0 is facility 16, info is severity 6
+        assert!(message.starts_with("<134>"), "{}", message);
+        assert!(message.ends_with(&format!(" totals[{}]: result 1,234.5", std::process::id())), "{}", message);
+    }
+
+    #[test]
+    fn test_facility() {
+        assert!(matches!(facility("local0"), Ok(Facility::LOG_LOCAL0)));
+        assert!(matches!(facility("LOG_DAEMON"), Ok(Facility::LOG_DAEMON)));
+        assert!(facility("local9").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use std::cell::Cell;
+
+/// Read a timestamp as seconds since the Unix epoch: either that number itself, or an ISO 8601
+/// date and time, like 2024-03-01T12:00:00Z. Times without an offset are taken to be UTC, and a
+/// date alone is its midnight.
+pub fn seconds(s: &str) -> Option<f64> {
+    if let Ok(epoch) = s.parse::<f64>() {
+        return Some(epoch).filter(|epoch| epoch.is_finite())
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.timestamp_micros() as f64 / 1e6)
+    }
+    let naive = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))?;
+    Some(naive.and_utc().timestamp_micros() as f64 / 1e6)
+}
+
+/// The earliest and latest timestamp seen, for --timestamp-column.
+#[derive(Default)]
+pub struct Span {
+    bounds: Cell<Option<(f64, f64)>>,
+}
+
+impl Span {
+    pub fn record(&self, seconds: f64) {
+        self.bounds.set(Some(match self.bounds.get() {
+            Some((first, last)) => (first.min(seconds), last.max(seconds)),
+            None => (seconds, seconds)
+        }));
+    }
+
+    /// The seconds from the earliest timestamp to the latest, `None` before any.
+    pub fn seconds(&self) -> Option<f64> {
+        self.bounds.get().map(|(first, last)| last - first)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Span, seconds};
+
+    #[test]
+    fn test_epoch_seconds() {
+        assert_eq!(Some(1700000000.), seconds("1700000000"));
+        assert_eq!(Some(1.5), seconds("1.5"));
+        assert_eq!(None, seconds("inf"));
+    }
+
+    #[test]
+    fn test_iso_8601() {
+        assert_eq!(Some(1709294400.), seconds("2024-03-01T12:00:00Z"));
+        assert_eq!(Some(1709294400.), seconds("2024-03-01T13:00:00+01:00"));
+        assert_eq!(Some(1709294400.25), seconds("2024-03-01T12:00:00.25"));
+        assert_eq!(Some(1709294400.), seconds("2024-03-01 12:00:00"));
+        assert_eq!(Some(1709251200.), seconds("2024-03-01"));
+        assert_eq!(None, seconds("yesterday"));
+        assert_eq!(None, seconds("2024-13-01"));
+    }
+
+    #[test]
+    fn test_span() {
+        let span = Span::default();
+        assert_eq!(None, span.seconds());
+        span.record(100.);
+        assert_eq!(Some(0.), span.seconds());
+        span.record(160.);
+        span.record(90.);
+        assert_eq!(Some(70.), span.seconds());
+    }
+}
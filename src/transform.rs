@@ -0,0 +1,255 @@
+/// Express every value as a percentage of the sum of all of them.
+pub fn percent_of_total(values: &[f64]) -> Result<Vec<f64>, String> {
+    let total: f64 = values.iter().sum();
+    if total == 0. {
+        return Err("Can't take percentages of a total of 0".to_string())
+    }
+    Ok(values.iter().map(|v| v / total * 100.).collect())
+}
+
+/// The softmax of `values`: e to the power of each, divided by the sum of them all, so they add
+/// up to 1 and keep their order. The largest value is taken off every value first, which leaves
+/// the result the same but keeps the powers from overflowing for values above about 709.
+pub fn softmax(values: &[f64]) -> Vec<f64> {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let powers: Vec<f64> = values.iter().map(|v| (v - max).exp()).collect();
+    let total: f64 = powers.iter().sum();
+    powers.into_iter().map(|p| p / total).collect()
+}
+
+/// The percent change from each value to the next, with the value it changed to. A change from
+/// 0 is an error.
+pub fn pct_change(values: impl Iterator<Item=f64>) -> impl Iterator<Item=Result<(f64, f64), String>> {
+    let mut previous = None;
+    values.filter_map(move |val| {
+        let change = previous.map(|prev: f64| match prev == 0. {
+            true => Err(format!("Can't take the percent change from 0 to {}", val)),
+            false => Ok((val, (val - prev) / prev * 100.))
+        });
+        previous = Some(val);
+        change
+    })
+}
+
+/// `values` without the ones more than `threshold` standard deviations from their mean, with
+/// the mean and the population standard deviation taken over all of them. When they're all the
+/// same nothing is dropped.
+pub fn zscore_filter(values: Vec<f64>, threshold: f64) -> Vec<f64> {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let stddev = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    match stddev > 0. {
+        true => values.into_iter().filter(|v| ((v - mean) / stddev).abs() <= threshold).collect(),
+        false => values
+    }
+}
+
+/// `values` with each run of repeats collapsed to its first value. A value within `epsilon` of
+/// the one before it counts as a repeat, so a slow drift can make one long run.
+pub fn dedup_consecutive(values: impl Iterator<Item=f64>, epsilon: f64) -> impl Iterator<Item=f64> {
+    let mut previous = None;
+    values.filter(move |&val| {
+        let repeat = previous.is_some_and(|prev: f64| (val - prev).abs() <= epsilon);
+        previous = Some(val);
+        !repeat
+    })
+}
+
+/// The mean of `values` after dropping the lowest and highest `percent` of them, rounding the
+/// number dropped from each end down.
+pub fn trimmed_mean(mut values: Vec<f64>, percent: f64, kahan: bool) -> Result<f64, String> {
+    values.sort_by(f64::total_cmp);
+    let trim = (values.len() as f64 * percent / 100.) as usize;
+    let kept = &values[trim..values.len() - trim];
+    if kept.is_empty() {
+        return Err(format!("No values left to average after trimming {}% of {}", percent, values.len()))
+    }
+    let total = match kahan {
+        true => compensated_sum(kept.iter().copied(), None).unwrap_or(0.),
+        false => kept.iter().sum()
+    };
+    Ok(total / kept.len() as f64)
+}
+
+/// The number of distinct `values`, taking values within `epsilon` of their neighbour in sorted
+/// order to be the same.
+pub fn count_distinct(mut values: Vec<f64>, epsilon: f64) -> usize {
+    values.sort_by(f64::total_cmp);
+    match values.is_empty() {
+        true => 0,
+        false => 1 + values.windows(2).filter(|pair| pair[1] - pair[0] > epsilon).count()
+    }
+}
+
+/// A fingerprint of `values` in order: the 64-bit FNV-1a hash of the little-endian bytes of each
+/// value's `f64` bit pattern. Streams of the same values in the same order hash the same; 0 and
+/// -0 differ, as do NaNs with different payloads.
+pub fn checksum(values: impl Iterator<Item=f64>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    values.flat_map(|val| val.to_bits().to_le_bytes())
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+/// The sum of `values` after `start`, with the Kahan-Babuška (Neumaier) correction: the low
+/// order bits each addition rounds away are added up on the side and put back at the end.
+pub fn compensated_sum(mut values: impl Iterator<Item=f64>, start: Option<f64>) -> Option<f64> {
+    let mut sum = match start {
+        Some(start) => start,
+        None => values.next()?
+    };
+    let mut compensation = 0.;
+    for val in values {
+        let total = sum + val;
+        compensation += match sum.abs() >= val.abs() {
+            true => (sum - total) + val,
+            false => (val - total) + sum
+        };
+        sum = total;
+    }
+    Some(sum + compensation)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{checksum, compensated_sum, count_distinct, dedup_consecutive, pct_change, percent_of_total, softmax, trimmed_mean, zscore_filter};
+
+    #[test]
+    fn test_percent_of_total() {
+        assert_eq!(Ok(vec![25., 25., 50.]), percent_of_total(&[1., 1., 2.]));
+    }
+
+    #[test]
+    fn test_percent_of_total_sums_to_100() {
+        let percents = percent_of_total(&[3., 7., 11., 13.5, 0.25]).unwrap();
+        assert!((percents.iter().sum::<f64>() - 100.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_percent_of_zero_total() {
+        assert!(percent_of_total(&[1., -1.]).is_err());
+        assert!(percent_of_total(&[]).is_err());
+    }
+
+    #[test]
+    fn test_trimmed_mean_drops_outliers() {
+        let values = vec![10., 12., 1000., 11., 9., -500., 10., 11., 8., 9.];
+        assert_eq!(Ok(10.), trimmed_mean(values.clone(), 10., false));
+        assert_eq!(Ok(58.), trimmed_mean(values, 0., false));
+    }
+
+    #[test]
+    fn test_trimmed_mean_rounds_down() {
+        // 10% of 5 values trims nothing
+        assert_eq!(Ok(3.), trimmed_mean(vec![1., 2., 3., 4., 5.], 10., false));
+        assert_eq!(Ok(3.), trimmed_mean(vec![1., 2., 3., 4., 5.], 49., false));
+    }
+
+    #[test]
+    fn test_trimmed_mean_nothing_left() {
+        // less than half is trimmed from each end, so only an empty input leaves nothing
+        assert_eq!(Ok(1.5), trimmed_mean(vec![1., 2.], 49., false));
+        assert!(trimmed_mean(vec![], 10., false).is_err());
+    }
+
+    #[test]
+    fn test_count_distinct() {
+        assert_eq!(3, count_distinct(vec![3., 1., 2., 3., 1., 1.], 0.));
+        assert_eq!(1, count_distinct(vec![0., -0.], 0.));
+        assert_eq!(0, count_distinct(vec![], 0.));
+    }
+
+    #[test]
+    fn test_count_distinct_near_duplicates() {
+        let values = vec![1., 1.001, 2., 1.999, 5.];
+        assert_eq!(5, count_distinct(values.clone(), 0.));
+        assert_eq!(3, count_distinct(values, 0.01));
+        // every value is within epsilon of the next one
+        assert_eq!(1, count_distinct(vec![1., 1.05, 1.1, 1.15], 0.06));
+    }
+
+    #[test]
+    fn test_pct_change_increasing() {
+        let changes: Result<Vec<(f64, f64)>, String> = pct_change(vec![100., 150., 300.].into_iter()).collect();
+        assert_eq!(Ok(vec![(150., 50.), (300., 100.)]), changes);
+        assert_eq!(0, pct_change(vec![7.].into_iter()).count());
+    }
+
+    #[test]
+    fn test_pct_change_decreasing() {
+        let changes: Result<Vec<(f64, f64)>, String> = pct_change(vec![200., 150., -75.].into_iter()).collect();
+        assert_eq!(Ok(vec![(150., -25.), (-75., -150.)]), changes);
+    }
+
+    #[test]
+    fn test_pct_change_from_zero() {
+        let changes: Vec<Result<(f64, f64), String>> = pct_change(vec![1., 0., 5.].into_iter()).collect();
+        assert_eq!(Ok((0., -100.)), changes[0]);
+        assert!(changes[1].is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let values = vec![1., 2.5, -3.];
+        assert_eq!(checksum(values.clone().into_iter()), checksum(values.clone().into_iter()));
+        assert_ne!(checksum(values.into_iter()), checksum(vec![1., 2.5, -3.5].into_iter()));
+        // order matters, unlike a sum
+        assert_ne!(checksum(vec![1., 2.].into_iter()), checksum(vec![2., 1.].into_iter()));
+        assert_eq!(0xcbf29ce484222325, checksum(std::iter::empty()));
+    }
+
+    #[test]
+    fn test_zscore_filter() {
+        // mean 19, standard deviation 27, so 100 is 3 deviations out
+        let mut values = vec![10.; 9];
+        values.push(100.);
+        assert_eq!(vec![10.; 9], zscore_filter(values.clone(), 2.));
+        assert_eq!(values.clone(), zscore_filter(values, 3.));
+        assert_eq!(vec![5., -20., 5., 5.], zscore_filter(vec![5., -20., 5., 5.], 2.));
+        assert_eq!(vec![4., 4.], zscore_filter(vec![4., 4.], 0.));
+        assert!(zscore_filter(Vec::new(), 2.).is_empty());
+    }
+
+    #[test]
+    fn test_compensated_sum() {
+        // every 1 is lost to rounding next to 1e16, but adds up to 10000 on the side
+        let values = || std::iter::once(1e16).chain(std::iter::repeat_n(1., 10000)).chain(std::iter::once(-1e16));
+        assert_eq!(Some(0.), values().reduce(|a, b| a + b));
+        assert_eq!(Some(10000.), compensated_sum(values(), None));
+        let tenths = || std::iter::repeat_n(0.1, 10);
+        assert_ne!(1., tenths().sum::<f64>());
+        assert_eq!(Some(1.), compensated_sum(tenths(), None));
+        assert_eq!(Some(3.), compensated_sum(tenths(), Some(2.)));
+        assert_eq!(None, compensated_sum(std::iter::empty(), None));
+    }
+
+    #[test]
+    fn test_trimmed_mean_kahan() {
+        assert_eq!(Ok(0.1), trimmed_mean(vec![0.1; 10], 0., true));
+        assert_ne!(Ok(0.1), trimmed_mean(vec![0.1; 10], 0., false));
+    }
+
+    #[test]
+    fn test_dedup_consecutive() {
+        let dedup = |values: &[f64], epsilon| dedup_consecutive(values.iter().copied(), epsilon).collect::<Vec<f64>>();
+        assert_eq!(vec![1., 2., 1.], dedup(&[1., 1., 2., 2., 1.], 0.));
+        assert_eq!(vec![1., 2., 1.], dedup(&[1., 1.05, 2., 1.98, 2.01, 1.], 0.1));
+        assert_eq!(vec![1., 1.05, 2., 1.98, 2.01, 1.], dedup(&[1., 1.05, 2., 1.98, 2.01, 1.], 0.));
+        assert_eq!(Vec::<f64>::new(), dedup(&[], 0.));
+    }
+
+    #[test]
+    fn test_softmax() {
+        let values = [1., 3., 2., -1.];
+        let probabilities = softmax(&values);
+        assert!((probabilities.iter().sum::<f64>() - 1.).abs() < 1e-12);
+        assert!(probabilities[1] > probabilities[2] && probabilities[2] > probabilities[0] && probabilities[0] > probabilities[3]);
+        assert_eq!(vec![0.5, 0.5], softmax(&[4., 4.]));
+        // without taking off the largest value first every power would be infinite
+        let large = softmax(&[1000., 1000. + 2f64.ln()]);
+        assert!((large[0] - 1. / 3.).abs() < 1e-12 && (large[1] - 2. / 3.).abs() < 1e-12);
+        assert!(softmax(&[]).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{self, BufRead, Read};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// Reads lines on another thread for --wait-for-data, so a blank line can be held back until
+/// it's clear whether more data follows. A blank line followed by more data within `timeout` is
+/// left out; one followed by the end of the input, or by nothing for `timeout`, comes through to
+/// stop the input as usual.
+pub struct Patient {
+    lines: Receiver<io::Result<Vec<u8>>>,
+    line: Vec<u8>,
+    pos: usize,
+    timeout: Duration,
+}
+
+impl Patient {
+    /// Read the reader `open` makes on a thread of its own.
+    pub fn spawn<R: BufRead>(open: impl FnOnce() -> R + Send + 'static, timeout: Duration) -> Patient {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = open();
+            loop {
+                let mut line = Vec::new();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => if sender.send(Ok(line)).is_err() {
+                        break
+                    },
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break
+                    }
+                }
+            }
+        });
+        Patient { lines, line: Vec::new(), pos: 0, timeout }
+    }
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+impl Read for Patient {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let buf = self.fill_buf()?;
+            let n = buf.len().min(out.len());
+            out[..n].copy_from_slice(&buf[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for Patient {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            let mut line = match self.lines.recv() {
+                Ok(line) => line?,
+                Err(_) => return Ok(&[])
+            };
+            while is_blank(&line) {
+                match self.lines.recv_timeout(self.timeout) {
+                    Ok(next) => {
+                        log::debug!("More data followed a blank line, skipping it");
+                        line = next?;
+                    },
+                    Err(_) => break
+                }
+            }
+            self.line = line;
+            self.pos = 0;
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::Patient;
+    use std::io::{BufRead, Read};
+    use std::time::Duration;
+
+    /// Hands out `chunks` one read at a time, sleeping `pause` before each.
+    struct Slow {
+        chunks: Vec<&'static [u8]>,
+        pause: Duration,
+    }
+
+    impl Read for Slow {
+        fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+            if self.chunks.is_empty() {
+                return Ok(0)
+            }
+            std::thread::sleep(self.pause);
+            let chunk = self.chunks.remove(0);
+            out[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    fn lines(chunks: Vec<&'static [u8]>, pause: Duration, timeout: Duration) -> Vec<String> {
+        let patient = Patient::spawn(move || std::io::BufReader::new(Slow { chunks, pause }), timeout);
+        patient.lines().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_blank_line_in_slow_stream_is_skipped() {
+        let lines = lines(vec![b"1\n  \n", b"2\n"], Duration::from_millis(50), Duration::from_secs(5));
+        assert_eq!(vec!["1", "2"], lines);
+    }
+
+    #[test]
+    fn test_blank_line_before_silence_stops() {
+        let lines = lines(vec![b"1\n\n", b"2\n"], Duration::from_millis(300), Duration::from_millis(20));
+        assert_eq!(vec!["1", "", "2"], lines);
+    }
+
+    #[test]
+    fn test_blank_line_at_end_comes_through() {
+        let lines = lines(vec![b"1\n\n"], Duration::from_millis(0), Duration::from_secs(5));
+        assert_eq!(vec!["1", ""], lines);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// The statistic --running-window-stats reports for each window.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Stat {
+    Mean,
+    Min,
+    Max,
+}
+
+impl Stat {
+    fn of(self, window: &VecDeque<f64>) -> f64 {
+        match self {
+            Stat::Mean => window.iter().sum::<f64>() / window.len() as f64,
+            Stat::Min => window.iter().copied().fold(f64::INFINITY, f64::min),
+            Stat::Max => window.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+        }
+    }
+}
+
+impl FromStr for Stat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "mean" => Ok(Stat::Mean),
+            "min" => Ok(Stat::Min),
+            "max" => Ok(Stat::Max),
+            _ => Err(format!("Unknown statistic {}, options are mean, min, max", s))
+        }
+    }
+}
+
+/// The `stat` of the last `size` values, once for every value. Until `size` values have been
+/// seen, the window is just the values so far.
+pub fn rolling(values: impl Iterator<Item=f64>, size: usize, stat: Stat) -> impl Iterator<Item=f64> {
+    let mut window = VecDeque::with_capacity(size);
+    values.map(move |val| {
+        if window.len() == size {
+            window.pop_front();
+        }
+        window.push_back(val);
+        stat.of(&window)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Stat, rolling};
+
+    #[test]
+    fn test_rolling_mean() {
+        let means: Vec<f64> = rolling(vec![2., 4., 6., 8., 10.].into_iter(), 3, Stat::Mean).collect();
+        assert_eq!(vec![2., 3., 4., 6., 8.], means);
+    }
+
+    #[test]
+    fn test_rolling_min_max() {
+        let values = vec![5., 1., 3., 4., 2.];
+        let mins: Vec<f64> = rolling(values.clone().into_iter(), 2, Stat::Min).collect();
+        assert_eq!(vec![5., 1., 1., 3., 2.], mins);
+        let maxes: Vec<f64> = rolling(values.into_iter(), 2, Stat::Max).collect();
+        assert_eq!(vec![5., 5., 3., 4., 4.], maxes);
+    }
+
+    #[test]
+    fn test_window_of_one() {
+        let means: Vec<f64> = rolling(vec![1., -1., 3.].into_iter(), 1, Stat::Mean).collect();
+        assert_eq!(vec![1., -1., 3.], means);
+    }
+
+    #[test]
+    fn test_stat_from_str() {
+        assert_eq!(Ok(Stat::Max), "max".parse());
+        assert!("median".parse::<Stat>().is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run mathcli with `args`, feeding it `input` on stdin.
+fn mathcli(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mathcli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_strict_empty_ignored_line_fails() {
+    let output = mathcli(&["--strict", "-i", "2", "add"], "hdr\n\n1\n");
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+    let output = mathcli(&["-i", "2", "add"], "hdr\n\n1\n");
+    assert!(output.status.success());
+    assert_eq!(b"1\n", &output.stdout[..]);
+}
+
+#[test]
+fn test_strict_token_count_fails() {
+    let output = mathcli(&["--expect-tokens", "2", "--strict", "-c", "1", "add"], "1 a\n2\n3 c\n");
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+    let output = mathcli(&["--expect-tokens", "2", "-c", "1", "add"], "1 a\n2\n3 c\n");
+    assert_eq!(b"6\n", &output.stdout[..]);
+}
+
+#[test]
+fn test_unsorted_merge_input_is_reported() {
+    let dir = std::env::temp_dir();
+    let (sorted, unsorted) = (dir.join("mathcli_test_sorted.txt"), dir.join("mathcli_test_unsorted.txt"));
+    std::fs::write(&sorted, "1\n3\n").unwrap();
+    std::fs::write(&unsorted, "4\n2\n").unwrap();
+    let output = mathcli(&["--merge-sorted", "--emit-merged", "-f", sorted.to_str().unwrap(), "-f", unsorted.to_str().unwrap(), "add"], "");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Input 2 is not sorted, 2 follows 4"));
+    assert_eq!(b"1\n3\n4\n2\n", &output.stdout[..]);
+}